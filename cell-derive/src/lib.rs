@@ -0,0 +1,360 @@
+//! `#[derive(Cell)]` for ckb-contract-test-fram cell types.
+//!
+//! The four cell fields are found by name (`lock_arg`, `type_arg`, `data`, `witness`)
+//! or marked explicitly with `#[cell(lock_arg)]`, `#[cell(type_arg)]`, `#[cell(data)]`
+//! and `#[cell(witness)]`. `type_arg` and `witness` must be `Option`s.
+//!
+//! The molecule encoding of a field is taken from, in order:
+//! - `#[cell(encoding = "...")]` on the field, one of `struct`, `table`, `fixvec`, `dynvec` and `raw`,
+//! - the `FieldEncodings` field named `encodings` or marked `#[cell(encodings)]`,
+//! - the `CellEncodings` type named by `#[cell(encodings = "...")]` on the struct,
+//!   which is how generic cells fix the encodings of their aliases,
+//! - molecule struct encoding otherwise.
+//!
+//! `field_layouts` splits each field as it is encoded, so `Cell::malformed` knows the layouts of any derived cell.
+//!
+//! `try_from_arg` starts from `Self::default()`, so the type needs a `default()` constructor,
+//! generic types need `Default` and molecule (de)serializable type parameters, but for their `CellEncodings`.
+//! Failures are reported as `CellError`s naming the field.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitStr, Type};
+
+#[proc_macro_derive(Cell, attributes(cell))]
+pub fn derive_cell(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    LockArg,
+    TypeArg,
+    Data,
+    Witness,
+    Encodings,
+}
+
+impl Role {
+    fn from_field_name(name: &str) -> Option<Role> {
+        match name {
+            "lock_arg" => Some(Role::LockArg),
+            "type_arg" => Some(Role::TypeArg),
+            "data" => Some(Role::Data),
+            "witness" => Some(Role::Witness),
+            "encodings" => Some(Role::Encodings),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Role::LockArg => "lock_arg",
+            Role::TypeArg => "type_arg",
+            Role::Data => "data",
+            Role::Witness => "witness",
+            Role::Encodings => "encodings",
+        }
+    }
+}
+
+struct FieldSpec {
+    ident: Ident,
+    // an `Encoding` variant fixed by the attribute
+    encoding: Option<TokenStream2>,
+}
+
+#[derive(Default)]
+struct CellFields {
+    lock_arg: Option<FieldSpec>,
+    type_arg: Option<FieldSpec>,
+    data: Option<FieldSpec>,
+    witness: Option<FieldSpec>,
+    encodings_field: Option<Ident>,
+    // the `CellEncodings` type of the struct attribute
+    encodings_type: Option<Type>,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = parse_fields(input)?;
+    let name = &input.ident;
+
+    let lock_arg = required(&fields.lock_arg, Role::LockArg, input)?;
+    let type_arg = required(&fields.type_arg, Role::TypeArg, input)?;
+    let data = required(&fields.data, Role::Data, input)?;
+    let witness = required(&fields.witness, Role::Witness, input)?;
+
+    let get_lock_arg = encode_field(lock_arg, Role::LockArg, &fields);
+    let get_type_arg = encode_optional_field(type_arg, Role::TypeArg, &fields);
+    let get_data = encode_field(data, Role::Data, &fields);
+    let get_witness = encode_optional_field(witness, Role::Witness, &fields);
+    let lock_ident = &lock_arg.ident;
+    let type_ident = &type_arg.ident;
+    let data_ident = &data.ident;
+    let witness_ident = &witness.ident;
+    let decode_lock_arg = decode_field(lock_arg, Role::LockArg, &fields, quote!(lock_arg));
+    let decode_type_arg = decode_optional_field(type_arg, Role::TypeArg, &fields, quote!(type_arg));
+    let decode_data = decode_field(data, Role::Data, &fields, quote!(data1));
+    let decode_witness = decode_optional_field(witness, Role::Witness, &fields, quote!(witness_args));
+    let lock_arg_layout = field_layout(lock_arg, Role::LockArg, &fields);
+    let type_arg_layout = optional_field_layout(type_arg, Role::TypeArg, &fields);
+    let data_layout = field_layout(data, Role::Data, &fields);
+    let witness_layout = optional_field_layout(witness, Role::Witness, &fields);
+
+    // generic cells need their parameters to be molecule (de)serializable and `Default` to start decoding from,
+    // the parameter naming the encodings only needs to be `CellEncodings`
+    let mut generics = input.generics.clone();
+    if !generics.params.is_empty() || fields.encodings_type.is_some() {
+        let encodings_param = fields.encodings_type.as_ref().and_then(param_ident);
+        let params: Vec<Ident> = generics.type_params()
+            .map(|param| param.ident.clone())
+            .filter(|param| Some(param) != encodings_param.as_ref())
+            .collect();
+        let (_, ty_generics, _) = input.generics.split_for_impl();
+        let where_clause = generics.make_where_clause();
+        for param in params {
+            where_clause.predicates.push(syn::parse_quote!(#param: serde::Serialize + serde::de::DeserializeOwned));
+        }
+        if let Some(encodings) = &fields.encodings_type {
+            where_clause.predicates.push(syn::parse_quote!(#encodings: crate::cell_message::cell::CellEncodings));
+        }
+        if !input.generics.params.is_empty() {
+            where_clause.predicates.push(syn::parse_quote!(#name #ty_generics: Default));
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics crate::cell_message::cell::Cell for #name #ty_generics #where_clause {
+            fn try_get_lock_arg(&self) -> Result<Vec<u8>, crate::cell_message::error::CellError> {
+                #get_lock_arg
+            }
+
+            fn try_get_type_arg(&self) -> Result<Option<Vec<u8>>, crate::cell_message::error::CellError> {
+                #get_type_arg
+            }
+
+            fn try_get_data(&self) -> Result<Vec<u8>, crate::cell_message::error::CellError> {
+                #get_data
+            }
+
+            fn try_get_witness(&self) -> Result<Option<Vec<u8>>, crate::cell_message::error::CellError> {
+                #get_witness
+            }
+
+            fn try_from_arg(lock_arg: Vec<u8>, type_arg: Option<Vec<u8>>, data1: Vec<u8>, witness_args: Option<Vec<u8>>) -> Result<Self, crate::cell_message::error::CellError> {
+                let mut cell = Self::default();
+                cell.#lock_ident = #decode_lock_arg;
+                cell.#type_ident = #decode_type_arg;
+                cell.#data_ident = #decode_data;
+                cell.#witness_ident = #decode_witness;
+                Ok(cell)
+            }
+
+            fn field_layouts(&self) -> [Option<crate::cell_message::malformed::FieldLayout>; 4] {
+                [#lock_arg_layout, #type_arg_layout, #data_layout, #witness_layout]
+            }
+        }
+    })
+}
+
+fn field_variant(role: Role) -> TokenStream2 {
+    match role {
+        Role::LockArg => quote!(crate::cell_message::error::CellField::LockArg),
+        Role::TypeArg => quote!(crate::cell_message::error::CellField::TypeArg),
+        Role::Data => quote!(crate::cell_message::error::CellField::Data),
+        Role::Witness => quote!(crate::cell_message::error::CellField::Witness),
+        Role::Encodings => unreachable!("the encodings are not encoded"),
+    }
+}
+
+fn encode_field(field: &FieldSpec, role: Role, fields: &CellFields) -> TokenStream2 {
+    let ident = &field.ident;
+    let encoding = encoding(field, role, fields, &quote!(self));
+    let variant = field_variant(role);
+    quote! {
+        let encoding = #encoding;
+        encoding.encode(&self.#ident)
+            .map_err(|err| crate::cell_message::error::CellError::encode(#variant, encoding, err))
+    }
+}
+
+fn encode_optional_field(field: &FieldSpec, role: Role, fields: &CellFields) -> TokenStream2 {
+    let ident = &field.ident;
+    let encoding = encoding(field, role, fields, &quote!(self));
+    let variant = field_variant(role);
+    quote! {
+        let encoding = #encoding;
+        self.#ident.as_ref()
+            .map(|value| encoding.encode(value))
+            .transpose()
+            .map_err(|err| crate::cell_message::error::CellError::encode(#variant, encoding, err))
+    }
+}
+
+// the default value of the field, still in `cell` when decoding fails, locates length mismatches
+fn decode_field(field: &FieldSpec, role: Role, fields: &CellFields, bytes: TokenStream2) -> TokenStream2 {
+    let ident = &field.ident;
+    let encoding = encoding(field, role, fields, &quote!(cell));
+    let variant = field_variant(role);
+    quote! {{
+        let encoding = #encoding;
+        match encoding.decode(&#bytes) {
+            Ok(value) => value,
+            Err(err) => {
+                let expected = encoding.encode(&cell.#ident).ok();
+                return Err(crate::cell_message::error::CellError::decode(#variant, encoding, &#bytes, expected.as_deref(), err));
+            }
+        }
+    }}
+}
+
+fn decode_optional_field(field: &FieldSpec, role: Role, fields: &CellFields, bytes: TokenStream2) -> TokenStream2 {
+    let ident = &field.ident;
+    let encoding = encoding(field, role, fields, &quote!(cell));
+    let variant = field_variant(role);
+    quote! {{
+        let encoding = #encoding;
+        match #bytes {
+            None => None,
+            Some(bytes) => match encoding.decode(&bytes) {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    let expected = cell.#ident.as_ref().and_then(|value| encoding.encode(value).ok());
+                    return Err(crate::cell_message::error::CellError::decode(#variant, encoding, &bytes, expected.as_deref(), err));
+                }
+            },
+        }
+    }}
+}
+
+// the layout of the field as it is encoded, `None` if the encoding has none
+fn field_layout(field: &FieldSpec, role: Role, fields: &CellFields) -> TokenStream2 {
+    let ident = &field.ident;
+    let encoding = encoding(field, role, fields, &quote!(self));
+    quote! {
+        crate::cell_message::malformed::FieldLayout::of(&self.#ident, #encoding).ok()
+    }
+}
+
+fn optional_field_layout(field: &FieldSpec, role: Role, fields: &CellFields) -> TokenStream2 {
+    let ident = &field.ident;
+    let encoding = encoding(field, role, fields, &quote!(self));
+    quote! {
+        self.#ident.as_ref().and_then(|value| crate::cell_message::malformed::FieldLayout::of(value, #encoding).ok())
+    }
+}
+
+fn parse_fields(input: &DeriveInput) -> syn::Result<CellFields> {
+    let named = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(&input.ident, "#[derive(Cell)] needs a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(&input.ident, "#[derive(Cell)] only supports structs")),
+    };
+
+    let mut fields = CellFields::default();
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("cell")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("encodings") {
+                let value: LitStr = meta.value()?.parse()?;
+                fields.encodings_type = Some(value.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown cell attribute, a struct only takes `encodings`"))
+            }
+        })?;
+    }
+    for field in named {
+        let ident = field.ident.clone().unwrap();
+        let mut role = Role::from_field_name(&ident.to_string());
+        let mut field_encoding = None;
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("cell")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("lock_arg") {
+                    role = Some(Role::LockArg);
+                } else if meta.path.is_ident("type_arg") {
+                    role = Some(Role::TypeArg);
+                } else if meta.path.is_ident("data") {
+                    role = Some(Role::Data);
+                } else if meta.path.is_ident("witness") {
+                    role = Some(Role::Witness);
+                } else if meta.path.is_ident("encodings") {
+                    role = Some(Role::Encodings);
+                } else if meta.path.is_ident("skip") {
+                    role = None;
+                } else if meta.path.is_ident("encoding") {
+                    let value: LitStr = meta.value()?.parse()?;
+                    let variant = match value.value().as_str() {
+                        "struct" => quote!(Struct),
+                        "table" => quote!(Table),
+                        "fixvec" => quote!(FixVec),
+                        "dynvec" => quote!(DynVec),
+                        "raw" => quote!(Raw),
+                        other => return Err(meta.error(format!(
+                            "unknown encoding `{}`, expected one of \"struct\", \"table\", \"fixvec\", \"dynvec\" and \"raw\"",
+                            other
+                        ))),
+                    };
+                    field_encoding = Some(quote!(crate::cell_message::encoding::Encoding::#variant));
+                } else {
+                    return Err(meta.error("unknown cell attribute"));
+                }
+                Ok(())
+            })?;
+        }
+
+        let slot = match role {
+            None => continue,
+            Some(Role::Encodings) => {
+                if fields.encodings_field.replace(ident.clone()).is_some() {
+                    return Err(syn::Error::new_spanned(&ident, "duplicate encodings field"));
+                }
+                continue;
+            }
+            Some(Role::LockArg) => &mut fields.lock_arg,
+            Some(Role::TypeArg) => &mut fields.type_arg,
+            Some(Role::Data) => &mut fields.data,
+            Some(Role::Witness) => &mut fields.witness,
+        };
+        let cell_field = FieldSpec { ident: ident.clone(), encoding: field_encoding };
+        if slot.replace(cell_field).is_some() {
+            return Err(syn::Error::new_spanned(&ident, format!("duplicate {} field", role.unwrap().name())));
+        }
+    }
+    if let (Some(field), Some(_)) = (&fields.encodings_field, &fields.encodings_type) {
+        return Err(syn::Error::new_spanned(field, "the encodings come from either the encodings field or #[cell(encodings = \"...\")]"));
+    }
+    Ok(fields)
+}
+
+fn required<'a>(field: &'a Option<FieldSpec>, role: Role, input: &DeriveInput) -> syn::Result<&'a FieldSpec> {
+    field.as_ref().ok_or_else(|| {
+        syn::Error::new_spanned(&input.ident, format!("missing {} field, name it so or mark it with #[cell({})]", role.name(), role.name()))
+    })
+}
+
+// `&Encoding` expression of a field, read from `receiver` when it comes from the encodings field
+fn encoding(field: &FieldSpec, role: Role, fields: &CellFields, receiver: &TokenStream2) -> TokenStream2 {
+    let member = format_ident!("{}", role.name());
+    match (&field.encoding, &fields.encodings_field, &fields.encodings_type) {
+        (Some(encoding), _, _) => quote!(&#encoding),
+        (None, Some(encodings), _) => quote!(&#receiver.#encodings.#member),
+        (None, None, Some(encodings)) => quote!(&<#encodings as crate::cell_message::cell::CellEncodings>::encodings().#member),
+        (None, None, None) => quote!(&crate::cell_message::encoding::Encoding::Struct),
+    }
+}
+
+// the type parameter a type is, if it is a bare identifier
+fn param_ident(ty: &Type) -> Option<Ident> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident().cloned(),
+        _ => None,
+    }
+}
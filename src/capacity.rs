@@ -0,0 +1,118 @@
+use std::fmt;
+use std::ops::{Add, Sub};
+use crate::prelude::SHANNONS_PER_CKB;
+
+///
+/// The capacity of a cell `ContractUtil` creates: a number of shannons, or the occupied capacity
+/// of the cell (its lock, type, data and the 8 capacity bytes) plus a number of shannons.
+///
+/// ```ignore
+/// let tx = ct.tx()
+///     .input(&always_success, None, &cell, Capacity::ckb(1000))
+///     .output(&always_success, None, &cell, Capacity::shannons(999))         // too little for the cell
+///     .output(&always_success, None, &cell, Capacity::occupied_plus_ckb(1))  // whatever the cell occupies, plus 1 CKB
+///     .build();
+/// ```
+///
+/// Arithmetic is checked: `checked_add` and `checked_sub` return `None` on overflow, `+` and `-` panic.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Capacity {
+    shannons: u64,
+    plus_occupied: bool,
+}
+
+impl Capacity {
+    pub const ZERO: Capacity = Capacity::shannons(0);
+
+    pub const fn shannons(shannons: u64) -> Self {
+        Capacity { shannons, plus_occupied: false }
+    }
+
+    /// panics if `ckb` CKB don't fit in a u64 of shannons
+    pub const fn ckb(ckb: u64) -> Self {
+        match ckb.checked_mul(SHANNONS_PER_CKB) {
+            Some(shannons) => Capacity::shannons(shannons),
+            None => panic!("capacity overflow"),
+        }
+    }
+
+    /// exactly what the cell occupies
+    pub const fn occupied() -> Self {
+        Capacity { shannons: 0, plus_occupied: true }
+    }
+
+    /// what the cell occupies plus `ckb` CKB
+    pub const fn occupied_plus_ckb(ckb: u64) -> Self {
+        Capacity::occupied_plus_shannons(Capacity::ckb(ckb).shannons)
+    }
+
+    /// what the cell occupies plus `shannons`
+    pub const fn occupied_plus_shannons(shannons: u64) -> Self {
+        Capacity { shannons, plus_occupied: true }
+    }
+
+    /// the shannons, on top of the occupied capacity if `is_occupied_relative`
+    pub const fn as_u64(self) -> u64 {
+        self.shannons
+    }
+
+    pub const fn is_occupied_relative(self) -> bool {
+        self.plus_occupied
+    }
+
+    /// `None` on overflow, or when both are relative to the occupied capacity, which can't be counted twice
+    pub fn checked_add(self, rhs: Capacity) -> Option<Capacity> {
+        if self.plus_occupied && rhs.plus_occupied {
+            return None;
+        }
+        Some(Capacity {
+            shannons: self.shannons.checked_add(rhs.shannons)?,
+            plus_occupied: self.plus_occupied || rhs.plus_occupied,
+        })
+    }
+
+    /// `None` on underflow, or when only `rhs` is relative to the occupied capacity
+    pub fn checked_sub(self, rhs: Capacity) -> Option<Capacity> {
+        if rhs.plus_occupied && !self.plus_occupied {
+            return None;
+        }
+        Some(Capacity {
+            shannons: self.shannons.checked_sub(rhs.shannons)?,
+            plus_occupied: self.plus_occupied && !rhs.plus_occupied,
+        })
+    }
+
+    /// the capacity in shannons of a cell occupying `occupied` shannons, `None` on overflow
+    pub fn resolve(self, occupied: u64) -> Option<u64> {
+        if self.plus_occupied {
+            occupied.checked_add(self.shannons)
+        } else {
+            Some(self.shannons)
+        }
+    }
+}
+
+impl Add for Capacity {
+    type Output = Capacity;
+
+    fn add(self, rhs: Capacity) -> Capacity {
+        self.checked_add(rhs).unwrap_or_else(|| panic!("capacity overflow: {} + {}", self, rhs))
+    }
+}
+
+impl Sub for Capacity {
+    type Output = Capacity;
+
+    fn sub(self, rhs: Capacity) -> Capacity {
+        self.checked_sub(rhs).unwrap_or_else(|| panic!("capacity underflow: {} - {}", self, rhs))
+    }
+}
+
+impl fmt::Display for Capacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.plus_occupied {
+            write!(f, "occupied + ")?;
+        }
+        write!(f, "{} shannons", self.shannons)
+    }
+}
@@ -1,33 +1,113 @@
-use ckb_testtool::ckb_jsonrpc_types::{Deserialize, Serialize};
+use ckb_testtool::ckb_types::packed;
+use crate::cell_message::encoding::Encoding;
+use crate::cell_message::error::CellError;
+use crate::cell_message::malformed::{FieldLayout, MalformedCell};
+use crate::cell_message::witness_args::parse_witness_args;
+
+/// `#[derive(Cell)]`, see the `cell-derive` crate for the field attributes
+pub use cell_derive::Cell;
 
 pub trait Cell {
-    fn get_lock_arg(&self) -> Vec<u8>;
+    fn try_get_lock_arg(&self) -> Result<Vec<u8>, CellError>;
+    fn try_get_type_arg(&self) -> Result<Option<Vec<u8>>, CellError>;
+    fn try_get_data(&self) -> Result<Vec<u8>, CellError>;
+    fn try_get_witness(&self) -> Result<Option<Vec<u8>>, CellError>;
+
+    fn try_from_arg(lock_arg: Vec<u8>, type_arg: Option<Vec<u8>>, data1: Vec<u8>, witness_args: Option<Vec<u8>>) -> Result<Self, CellError> where Self: Sized;
+
+    fn get_lock_arg(&self) -> Vec<u8> {
+        self.try_get_lock_arg().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn get_type_arg(&self) -> Option<Vec<u8>> {
+        self.try_get_type_arg().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn get_data(&self) -> Vec<u8> {
+        self.try_get_data().unwrap_or_else(|err| panic!("{}", err))
+    }
 
-    fn get_type_arg(&self) -> Option<Vec<u8>>;
-    fn get_data(&self) -> Vec<u8>;
-    fn get_witness(&self) -> Option<Vec<u8>>;
+    fn get_witness(&self) -> Option<Vec<u8>> {
+        self.try_get_witness().unwrap_or_else(|err| panic!("{}", err))
+    }
 
-    fn from_arg(lock_arg: Vec<u8>, type_arg: Option<Vec<u8>>, data1: Vec<u8>, witness_args: Option<Vec<u8>>) -> Self where Self: Sized ;
+    fn from_arg(lock_arg: Vec<u8>, type_arg: Option<Vec<u8>>, data1: Vec<u8>, witness_args: Option<Vec<u8>>) -> Self where Self: Sized {
+        Self::try_from_arg(lock_arg, type_arg, data1, witness_args).unwrap_or_else(|err| panic!("{}", err))
+    }
 
+    /// the witness as molecule `WitnessArgs`, `None` if there is no witness or it is not a valid `WitnessArgs`
+    fn get_witness_args(&self) -> Option<packed::WitnessArgs> {
+        self.get_witness().and_then(|witness| parse_witness_args(&witness))
+    }
 
+    ///
+    /// The top-level field layouts of lock args, type args, data and witness, as they are encoded,
+    /// `None` for a missing field or one whose encoding has no layout. `#[derive(Cell)]` implements it.
+    fn field_layouts(&self) -> [Option<FieldLayout>; 4] {
+        Default::default()
+    }
+
+    /// a `MalformedCell` of this cell with the layouts of its fields
+    fn malformed(&self) -> MalformedCell where Self: Sized {
+        MalformedCell::from_cell(self).with_layouts(self.field_layouts())
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct MoleculeStructFlag {
-    pub lock_arg: bool,
-    pub type_arg: bool,
-    pub data: bool,
-    pub witness: bool,
+/// encoding of each cell field
+#[derive(Clone, PartialEq, Debug)]
+pub struct FieldEncodings {
+    pub lock_arg: Encoding,
+    pub type_arg: Encoding,
+    pub data: Encoding,
+    pub witness: Encoding,
 }
 
 
-impl MoleculeStructFlag {
+impl FieldEncodings {
     pub(crate) fn default() -> Self {
-        MoleculeStructFlag {
-            lock_arg: true,
-            type_arg: true,
-            data: true,
-            witness: true,
+        FieldEncodings {
+            lock_arg: Encoding::Struct,
+            type_arg: Encoding::Struct,
+            data: Encoding::Struct,
+            witness: Encoding::Struct,
         }
     }
+
+    /// encodings of a cell whose witness is a `WitnessArgs` table
+    pub(crate) fn with_witness_args() -> Self {
+        FieldEncodings {
+            witness: Encoding::Table,
+            ..Self::default()
+        }
+    }
+}
+
+///
+/// The field encodings of a cell type, so that a cell decodes with them without a value to read them from.
+/// Aliases of `MoleculeCell` name theirs as the last type parameter:
+/// ```ignore
+/// pub type XudtCell = MoleculeCell<u8, [u8; 32], XUDTData, XUDTWitness, WitnessArgsEncodings>;
+/// ```
+pub trait CellEncodings {
+    fn encodings() -> FieldEncodings;
+}
+
+/// molecule struct encoding for every field
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct StructEncodings;
+
+impl CellEncodings for StructEncodings {
+    fn encodings() -> FieldEncodings {
+        FieldEncodings::default()
+    }
+}
+
+/// molecule struct encoding, but for a `WitnessArgs` table witness
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct WitnessArgsEncodings;
+
+impl CellEncodings for WitnessArgsEncodings {
+    fn encodings() -> FieldEncodings {
+        FieldEncodings::with_witness_args()
+    }
 }
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::rc::Rc;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_molecule::{dynvec_serde, from_slice, to_vec};
+use crate::cell_message::raw::{from_raw, to_raw};
+
+/// how a cell field is laid out on chain
+#[derive(Clone, PartialEq, Debug)]
+pub enum Encoding {
+    /// molecule struct
+    Struct,
+    /// molecule table
+    Table,
+    /// molecule fixvec of the items of a sequence
+    FixVec,
+    /// molecule dynvec of the items of a sequence, each item a table
+    DynVec,
+    /// bytes back to back with no molecule header, see `raw`
+    Raw,
+    Custom(CustomEncoding),
+}
+
+impl Encoding {
+    ///
+    /// a custom encoding, `encode` maps the raw encoding of the value to the bytes on chain
+    /// and `decode` maps them back
+    pub fn custom(
+        name: &'static str,
+        encode: impl Fn(Vec<u8>) -> Vec<u8> + 'static,
+        decode: impl Fn(&[u8]) -> Result<Vec<u8>, String> + 'static,
+    ) -> Self {
+        Encoding::Custom(CustomEncoding {
+            name,
+            encode: Rc::new(encode),
+            decode: Rc::new(decode),
+        })
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Struct => to_vec(value, true).map_err(|err| err.to_string()),
+            Encoding::Table => to_vec(value, false).map_err(|err| err.to_string()),
+            Encoding::FixVec => strip_field_header(to_vec(&FixVecRef { items: value }, false).map_err(|err| err.to_string())?),
+            Encoding::DynVec => strip_field_header(to_vec(&DynVecRef { items: value }, false).map_err(|err| err.to_string())?),
+            Encoding::Raw => to_raw(value).map_err(|err| err.to_string()),
+            Encoding::Custom(custom) => Ok((custom.encode)(to_raw(value).map_err(|err| err.to_string())?)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Encoding::Struct => from_slice(bytes, true).map_err(|err| err.to_string()),
+            Encoding::Table => from_slice(bytes, false).map_err(|err| err.to_string()),
+            Encoding::FixVec => from_slice::<FixVecOwned<T>>(&with_field_header(bytes), false)
+                .map(|vec| vec.items)
+                .map_err(|err| err.to_string()),
+            Encoding::DynVec => from_slice::<DynVecOwned<T>>(&with_field_header(bytes), false)
+                .map(|vec| vec.items)
+                .map_err(|err| err.to_string()),
+            Encoding::Raw => from_raw(bytes).map_err(|err| err.to_string()),
+            Encoding::Custom(custom) => from_raw(&(custom.decode)(bytes)?).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Struct => write!(f, "molecule struct"),
+            Encoding::Table => write!(f, "molecule table"),
+            Encoding::FixVec => write!(f, "molecule fixvec"),
+            Encoding::DynVec => write!(f, "molecule dynvec"),
+            Encoding::Raw => write!(f, "raw bytes"),
+            Encoding::Custom(custom) => write!(f, "custom encoding `{}`", custom.name),
+        }
+    }
+}
+
+type EncodeFn = dyn Fn(Vec<u8>) -> Vec<u8>;
+type DecodeFn = dyn Fn(&[u8]) -> Result<Vec<u8>, String>;
+
+#[derive(Clone)]
+pub struct CustomEncoding {
+    pub name: &'static str,
+    encode: Rc<EncodeFn>,
+    decode: Rc<DecodeFn>,
+}
+
+impl PartialEq for CustomEncoding {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Rc::ptr_eq(&self.encode, &other.encode) && Rc::ptr_eq(&self.decode, &other.decode)
+    }
+}
+
+impl fmt::Debug for CustomEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomEncoding").field("name", &self.name).finish()
+    }
+}
+
+// fixvec and dynvec are encoded as the only field of a table, whose 8 byte header (full size, offset) is dropped
+const FIELD_HEADER_LEN: usize = 8;
+
+#[derive(Serialize)]
+struct FixVecRef<'a, T: Serialize> {
+    items: &'a T,
+}
+
+#[derive(Serialize)]
+struct DynVecRef<'a, T: Serialize> {
+    #[serde(with = "dynvec_serde")]
+    items: &'a T,
+}
+
+#[derive(Deserialize)]
+struct FixVecOwned<T> {
+    items: T,
+}
+
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+struct DynVecOwned<T> {
+    #[serde(with = "dynvec_serde")]
+    items: T,
+}
+
+fn strip_field_header(table: Vec<u8>) -> Result<Vec<u8>, String> {
+    if table.len() < FIELD_HEADER_LEN {
+        return Err(format!("a one field table is at least {} bytes, got {}", FIELD_HEADER_LEN, table.len()));
+    }
+    Ok(table[FIELD_HEADER_LEN..].to_vec())
+}
+
+fn with_field_header(bytes: &[u8]) -> Vec<u8> {
+    let full_size = (FIELD_HEADER_LEN + bytes.len()) as u32;
+    [
+        full_size.to_le_bytes().to_vec(),
+        (FIELD_HEADER_LEN as u32).to_le_bytes().to_vec(),
+        bytes.to_vec(),
+    ].concat()
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use crate::cell_message::encoding::Encoding;
+
+/// the cell field that failed to encode or decode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CellField {
+    LockArg,
+    TypeArg,
+    Data,
+    Witness,
+}
+
+impl fmt::Display for CellField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CellField::LockArg => "lock_arg",
+            CellField::TypeArg => "type_arg",
+            CellField::Data => "data",
+            CellField::Witness => "witness",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CellOp {
+    Encode,
+    Decode,
+}
+
+/// an encoding / decoding failure of one cell field
+#[derive(Clone, PartialEq, Debug)]
+pub struct CellError {
+    pub field: CellField,
+    pub op: CellOp,
+    pub encoding: Encoding,
+    /// length of the bytes being decoded, 0 when encoding
+    pub len: usize,
+    /// byte offset in the field where decoding went wrong, when it can be located
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+impl CellError {
+    pub fn encode(field: CellField, encoding: &Encoding, message: impl fmt::Display) -> Self {
+        CellError {
+            field,
+            op: CellOp::Encode,
+            encoding: encoding.clone(),
+            len: 0,
+            offset: None,
+            message: message.to_string(),
+        }
+    }
+
+    ///
+    /// `expected` is the encoding of a value of the field type when one is at hand
+    /// (e.g. the field of `Self::default()`), it locates length mismatches of fixed size encodings
+    pub fn decode(field: CellField, encoding: &Encoding, bytes: &[u8], expected: Option<&[u8]>, message: impl fmt::Display) -> Self {
+        let offset = match encoding {
+            Encoding::Table | Encoding::DynVec => table_header_offset(bytes),
+            Encoding::FixVec => fixvec_header_offset(bytes),
+            Encoding::Struct | Encoding::Raw | Encoding::Custom(_) => {
+                expected.and_then(|expected| struct_mismatch_offset(bytes, expected))
+            }
+        };
+        CellError {
+            field,
+            op: CellOp::Decode,
+            encoding: encoding.clone(),
+            len: bytes.len(),
+            offset,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.op {
+            CellOp::Encode => "encode",
+            CellOp::Decode => "decode",
+        };
+        write!(f, "failed to {} {} as {}", op, self.field, self.encoding)?;
+        if self.op == CellOp::Decode {
+            write!(f, " ({} bytes", self.len)?;
+            if let Some(offset) = self.offset {
+                write!(f, ", at byte {}", offset)?;
+            }
+            write!(f, ")")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for CellError {}
+
+/// why an output of a tx is not the requested cell
+#[derive(Clone, PartialEq, Debug)]
+pub enum OutputError {
+    /// the tx has `outputs` outputs, none at `index`
+    Missing { index: usize, outputs: usize },
+    /// the tx has fewer outputs data than outputs
+    MissingData { index: usize, outputs_data: usize },
+    /// the output does not decode as the cell
+    Cell(CellError),
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::Missing { index, outputs } => write!(f, "tx has {} outputs, no output {}", outputs, index),
+            OutputError::MissingData { index, outputs_data } => write!(f, "tx has {} outputs data, no data of output {}", outputs_data, index),
+            OutputError::Cell(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+impl From<CellError> for OutputError {
+    fn from(err: CellError) -> Self {
+        OutputError::Cell(err)
+    }
+}
+
+// a molecule struct has a fixed size, decoding runs out of bytes or leaves some at the shorter length
+fn struct_mismatch_offset(bytes: &[u8], expected: &[u8]) -> Option<usize> {
+    if bytes.len() == expected.len() {
+        None
+    } else {
+        Some(bytes.len().min(expected.len()))
+    }
+}
+
+// a fixvec starts with its item count
+fn fixvec_header_offset(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 4 {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+// offset of the first inconsistent header word of a molecule table (or dynvec, which has the same header)
+fn table_header_offset(bytes: &[u8]) -> Option<usize> {
+    let read_u32 = |at: usize| -> Option<usize> {
+        bytes.get(at..at + 4).map(|word| u32::from_le_bytes(word.try_into().unwrap()) as usize)
+    };
+    let total_size = match read_u32(0) {
+        Some(size) if size == bytes.len() => size,
+        _ => return Some(0),
+    };
+    if total_size == 4 {
+        // empty table
+        return None;
+    }
+    let first_offset = match read_u32(4) {
+        Some(offset) if offset % 4 == 0 && offset >= 8 && offset <= total_size => offset,
+        _ => return Some(4),
+    };
+    let mut previous = first_offset;
+    for position in (8..first_offset).step_by(4) {
+        match read_u32(position) {
+            Some(offset) if offset >= previous && offset <= total_size => previous = offset,
+            _ => return Some(position),
+        }
+    }
+    None
+}
@@ -0,0 +1,402 @@
+//! malformed variants of a valid cell for negative length / layout tests:
+//! ```ignore
+//! let cell = MalformedCell::from_cell(&commitment_cell).extend(CellField::LockArg, &[0; 2]);
+//! let cell = commitment_cell.malformed().drop_field(CellField::Witness, 4);
+//! for cell in funding_cell.malformed().variants(CellField::Witness) { ... }
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+use serde::ser::{self, Impossible, Serialize, Serializer};
+use crate::cell_message::cell::Cell;
+use crate::cell_message::encoding::Encoding;
+use crate::cell_message::error::CellField;
+
+/// the encoded bytes of a cell, mutated field by field
+#[derive(Cell, Clone, PartialEq, Debug, Default)]
+pub struct MalformedCell {
+    #[cell(encoding = "raw")]
+    pub lock_arg: Vec<u8>,
+    #[cell(encoding = "raw")]
+    pub type_arg: Option<Vec<u8>>,
+    #[cell(encoding = "raw")]
+    pub data: Vec<u8>,
+    #[cell(encoding = "raw")]
+    pub witness: Option<Vec<u8>>,
+    /// the mutations applied so far
+    pub description: String,
+    layouts: [Option<FieldLayout>; 4],
+}
+
+impl MalformedCell {
+    /// a copy of the encoded `cell`, field layouts are unknown so `drop_field` needs `with_layout` first, see `Cell::malformed`
+    pub fn from_cell(cell: &dyn Cell) -> Self {
+        MalformedCell {
+            lock_arg: cell.get_lock_arg(),
+            type_arg: cell.get_type_arg(),
+            data: cell.get_data(),
+            witness: cell.get_witness(),
+            ..Self::default()
+        }
+    }
+
+    /// record the top-level fields of `field`, which is `value` encoded with `encoding`
+    pub fn with_layout<V: Serialize>(mut self, field: CellField, value: &V, encoding: &Encoding) -> Self {
+        let layout = FieldLayout::of(value, encoding).unwrap_or_else(|err| panic!("layout of {}: {}", field, err));
+        self.layouts[layout_index(field)] = Some(layout);
+        self
+    }
+
+    /// the layouts of all the fields, as `Cell::field_layouts` returns them
+    pub fn with_layouts(mut self, layouts: [Option<FieldLayout>; 4]) -> Self {
+        self.layouts = layouts;
+        self
+    }
+
+    pub fn layout(&self, field: CellField) -> Option<&FieldLayout> {
+        self.layouts[layout_index(field)].as_ref()
+    }
+
+    /// drop the last `len` bytes
+    pub fn truncate(self, field: CellField, len: usize) -> Self {
+        self.mutate(field, format!("{} truncated by {} bytes", field, len), |bytes| {
+            assert!(len <= bytes.len(), "can't truncate {} bytes of {}", len, bytes.len());
+            bytes.truncate(bytes.len() - len);
+        })
+    }
+
+    /// append `extra` bytes
+    pub fn extend(self, field: CellField, extra: &[u8]) -> Self {
+        self.mutate(field, format!("{} extended by {} bytes", field, extra.len()), |bytes| {
+            bytes.extend_from_slice(extra);
+        })
+    }
+
+    /// invert every bit of the byte at `offset`
+    pub fn flip_byte(self, field: CellField, offset: usize) -> Self {
+        self.mutate(field, format!("{} byte {} flipped", field, offset), |bytes| {
+            assert!(offset < bytes.len(), "byte {} out of {}", offset, bytes.len());
+            bytes[offset] ^= 0xff;
+        })
+    }
+
+    /// remove top-level field `index`, a table keeps a valid header
+    pub fn drop_field(self, field: CellField, index: usize) -> Self {
+        let layout = self.layout(field).cloned()
+            .unwrap_or_else(|| panic!("no layout of {}, build the cell with malformed() or with_layout()", field));
+        self.mutate(field, format!("{} field {} dropped", field, index), |bytes| {
+            *bytes = layout.drop(bytes, index);
+        })
+    }
+
+    ///
+    /// the standard variants of `field`: truncated and extended by one byte,
+    /// first and last byte flipped, and each top-level field dropped when the layout has several
+    pub fn variants(&self, field: CellField) -> Vec<MalformedCell> {
+        let len = self.part(field).map(|bytes| bytes.len()).unwrap_or(0);
+        let mut variants = vec![];
+        if len > 0 {
+            variants.push(self.clone().truncate(field, 1));
+        }
+        if self.part(field).is_some() {
+            variants.push(self.clone().extend(field, &[0]));
+        }
+        if len > 0 {
+            variants.push(self.clone().flip_byte(field, 0));
+        }
+        if len > 1 {
+            variants.push(self.clone().flip_byte(field, len - 1));
+        }
+        // dropping the only field would just empty it
+        if let Some(layout) = self.layout(field).filter(|layout| layout.ranges.len() > 1) {
+            for index in 0..layout.ranges.len() {
+                variants.push(self.clone().drop_field(field, index));
+            }
+        }
+        variants
+    }
+
+    /// `variants` of all the fields
+    pub fn all_variants(&self) -> Vec<MalformedCell> {
+        [CellField::LockArg, CellField::TypeArg, CellField::Data, CellField::Witness]
+            .into_iter()
+            .flat_map(|field| self.variants(field))
+            .collect()
+    }
+
+    fn part(&self, field: CellField) -> Option<&Vec<u8>> {
+        match field {
+            CellField::LockArg => Some(&self.lock_arg),
+            CellField::TypeArg => self.type_arg.as_ref(),
+            CellField::Data => Some(&self.data),
+            CellField::Witness => self.witness.as_ref(),
+        }
+    }
+
+    fn mutate(mut self, field: CellField, description: String, f: impl FnOnce(&mut Vec<u8>)) -> Self {
+        let bytes = match field {
+            CellField::LockArg => Some(&mut self.lock_arg),
+            CellField::TypeArg => self.type_arg.as_mut(),
+            CellField::Data => Some(&mut self.data),
+            CellField::Witness => self.witness.as_mut(),
+        };
+        f(bytes.unwrap_or_else(|| panic!("the cell has no {}", field)));
+        // the mutated bytes no longer follow the layout
+        self.layouts[layout_index(field)] = None;
+        if !self.description.is_empty() {
+            self.description.push_str(", ");
+        }
+        self.description.push_str(&description);
+        self
+    }
+}
+
+fn layout_index(field: CellField) -> usize {
+    match field {
+        CellField::LockArg => 0,
+        CellField::TypeArg => 1,
+        CellField::Data => 2,
+        CellField::Witness => 3,
+    }
+}
+
+/// byte ranges of the top-level fields of an encoded value
+#[derive(Clone, PartialEq, Debug)]
+pub struct FieldLayout {
+    pub ranges: Vec<Range<usize>>,
+    /// the fields follow a molecule table (or dynvec) header
+    pub table: bool,
+}
+
+impl FieldLayout {
+    pub fn of<V: Serialize>(value: &V, encoding: &Encoding) -> Result<Self, String> {
+        let bytes = encoding.encode(value)?;
+        match encoding {
+            Encoding::Table | Encoding::DynVec => Self::of_table(&bytes),
+            Encoding::Struct | Encoding::Raw => {
+                let mut splitter = FieldSplitter { encoding, fields: vec![] };
+                let lens: Vec<usize> = match value.serialize(&mut splitter) {
+                    Ok(()) => splitter.fields.iter().map(Vec::len).collect(),
+                    Err(SplitError::NotCompound) => vec![bytes.len()],
+                    Err(SplitError::Encode(err)) => return Err(err),
+                };
+                let mut ranges = Vec::with_capacity(lens.len());
+                let mut start = 0;
+                for len in lens {
+                    ranges.push(start..start + len);
+                    start += len;
+                }
+                if start != bytes.len() {
+                    return Err(format!("fields add up to {} bytes, the value is {}", start, bytes.len()));
+                }
+                Ok(FieldLayout { ranges, table: false })
+            }
+            other => Err(format!("field layout of {} is not supported", other)),
+        }
+    }
+
+    fn of_table(bytes: &[u8]) -> Result<Self, String> {
+        let read_u32 = |at: usize| -> Result<usize, String> {
+            bytes.get(at..at + 4)
+                .map(|word| u32::from_le_bytes(word.try_into().unwrap()) as usize)
+                .ok_or_else(|| format!("table header truncated at byte {}", at))
+        };
+        let total_size = read_u32(0)?;
+        if total_size == 4 {
+            return Ok(FieldLayout { ranges: vec![], table: true });
+        }
+        let first_offset = read_u32(4)?;
+        let mut offsets = vec![];
+        for position in (4..first_offset).step_by(4) {
+            offsets.push(read_u32(position)?);
+        }
+        offsets.push(total_size);
+        let ranges = offsets.windows(2).map(|pair| pair[0]..pair[1]).collect();
+        Ok(FieldLayout { ranges, table: true })
+    }
+
+    fn drop(&self, bytes: &[u8], index: usize) -> Vec<u8> {
+        assert!(index < self.ranges.len(), "field {} out of {}", index, self.ranges.len());
+        let fields: Vec<&[u8]> = self.ranges.iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .map(|(_, range)| &bytes[range.clone()])
+            .collect();
+        if !self.table {
+            return fields.concat();
+        }
+        let header_len = 4 + 4 * fields.len();
+        let total_size = header_len + fields.iter().map(|field| field.len()).sum::<usize>();
+        let mut table = (total_size as u32).to_le_bytes().to_vec();
+        let mut offset = header_len;
+        for field in &fields {
+            table.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += field.len();
+        }
+        table.extend(fields.concat());
+        table
+    }
+}
+
+enum SplitError {
+    NotCompound,
+    Encode(String),
+}
+
+impl fmt::Debug for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitError::NotCompound => write!(f, "not a compound value"),
+            SplitError::Encode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+impl ser::Error for SplitError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SplitError::Encode(msg.to_string())
+    }
+}
+
+// encodes each top-level field of a struct on its own
+struct FieldSplitter<'a> {
+    encoding: &'a Encoding,
+    fields: Vec<Vec<u8>>,
+}
+
+impl FieldSplitter<'_> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SplitError> {
+        let bytes = self.encoding.encode(&Unsized(value)).map_err(SplitError::Encode)?;
+        self.fields.push(bytes);
+        Ok(())
+    }
+}
+
+// lets a `?Sized` field go through the `Sized` encoders
+struct Unsized<'a, T: ?Sized>(&'a T);
+
+impl<T: Serialize + ?Sized> Serialize for Unsized<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+macro_rules! not_compound {
+    ($($method:ident($($arg:ty),*);)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<(), SplitError> {
+                Err(SplitError::NotCompound)
+            }
+        )*
+    };
+}
+
+impl Serializer for &mut FieldSplitter<'_> {
+    type Ok = ();
+    type Error = SplitError;
+    type SerializeSeq = Impossible<(), SplitError>;
+    type SerializeTuple = Impossible<(), SplitError>;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), SplitError>;
+    type SerializeMap = Impossible<(), SplitError>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), SplitError>;
+
+    not_compound! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_i128(i128);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_u128(u128);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SplitError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), SplitError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<(), SplitError> {
+        Err(SplitError::NotCompound)
+    }
+
+    // arrays and vectors are one field
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SplitError> {
+        Err(SplitError::NotCompound)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SplitError> {
+        Err(SplitError::NotCompound)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, SplitError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, SplitError> {
+        Err(SplitError::NotCompound)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SplitError> {
+        Err(SplitError::NotCompound)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, SplitError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, SplitError> {
+        Err(SplitError::NotCompound)
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut FieldSplitter<'_> {
+    type Ok = ();
+    type Error = SplitError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SplitError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), SplitError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut FieldSplitter<'_> {
+    type Ok = ();
+    type Error = SplitError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), SplitError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<(), SplitError> {
+        Ok(())
+    }
+}
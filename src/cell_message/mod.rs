@@ -1,3 +1,7 @@
 pub mod cell;
-
-
+pub mod encoding;
+pub mod error;
+pub mod malformed;
+pub mod molecule_cell;
+pub mod raw;
+pub mod witness_args;
@@ -0,0 +1,36 @@
+use std::marker::PhantomData;
+use crate::cell_message::cell::{Cell, StructEncodings};
+
+///
+/// a cell made of a lock arg `L`, type arg `T`, data `D` and witness `W` encoded with the `CellEncodings` `E`,
+/// cells that only differ in these types are aliases of it:
+/// ```ignore
+/// pub type FundingCell = MoleculeCell<[u8; 20], u8, u8, FundingWitness>;
+/// ```
+#[derive(Cell, Clone, PartialEq, Debug)]
+#[cell(encodings = "E")]
+pub struct MoleculeCell<L, T, D, W, E = StructEncodings> {
+    pub lock_arg: L,
+    pub type_arg: Option<T>,
+    pub data: D,
+    pub witness: Option<W>,
+    _encodings: PhantomData<E>,
+}
+
+impl<L, T, D, W, E> MoleculeCell<L, T, D, W, E> {
+    pub fn new(lock_arg: L, type_arg: Option<T>, data: D, witness: Option<W>) -> Self {
+        MoleculeCell {
+            lock_arg,
+            type_arg,
+            data,
+            witness,
+            _encodings: PhantomData,
+        }
+    }
+}
+
+impl<L: Default, T, D: Default, W, E> Default for MoleculeCell<L, T, D, W, E> {
+    fn default() -> Self {
+        MoleculeCell::new(L::default(), None, D::default(), None)
+    }
+}
@@ -0,0 +1,419 @@
+//! raw bytes encoding: values are laid out back to back with no molecule header,
+//! integers in little endian, `Option::None` and units as nothing.
+//! A sequence of unknown length (`Vec`, bytes, strings) takes all the remaining bytes when decoding,
+//! so it can only be the last field.
+
+use std::fmt;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::{self, Impossible, Serialize};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RawError(String);
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for RawError {}
+
+impl ser::Error for RawError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        RawError(msg.to_string())
+    }
+}
+
+impl de::Error for RawError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        RawError(msg.to_string())
+    }
+}
+
+pub fn to_raw<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, RawError> {
+    let mut serializer = RawSerializer { output: Vec::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+pub fn from_raw<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, RawError> {
+    let mut deserializer = RawDeserializer { input: bytes };
+    let value = T::deserialize(&mut deserializer)?;
+    if !deserializer.input.is_empty() {
+        return Err(RawError(format!("{} trailing bytes", deserializer.input.len())));
+    }
+    Ok(value)
+}
+
+struct RawSerializer {
+    output: Vec<u8>,
+}
+
+fn unsupported<T>(what: &str) -> Result<T, RawError> {
+    Err(RawError(format!("{} is not supported by the raw encoding", what)))
+}
+
+impl ser::Serializer for &mut RawSerializer {
+    type Ok = ();
+    type Error = RawError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), RawError>;
+    type SerializeMap = Impossible<(), RawError>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), RawError>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), RawError> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), RawError> {
+        self.output.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), RawError> {
+        self.output.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<(), RawError> {
+        unsupported("f32")
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<(), RawError> {
+        unsupported("f64")
+    }
+
+    fn serialize_char(self, _v: char) -> Result<(), RawError> {
+        unsupported("char")
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), RawError> {
+        self.output.extend_from_slice(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), RawError> {
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), RawError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), RawError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), RawError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), RawError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str) -> Result<(), RawError> {
+        unsupported("enum")
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), RawError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _value: &T) -> Result<(), RawError> {
+        unsupported("enum")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self, RawError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, RawError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, RawError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, RawError> {
+        unsupported("enum")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, RawError> {
+        unsupported("map")
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, RawError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, RawError> {
+        unsupported("enum")
+    }
+}
+
+impl ser::SerializeSeq for &mut RawSerializer {
+    type Ok = ();
+    type Error = RawError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RawError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), RawError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut RawSerializer {
+    type Ok = ();
+    type Error = RawError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RawError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), RawError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut RawSerializer {
+    type Ok = ();
+    type Error = RawError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RawError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), RawError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut RawSerializer {
+    type Ok = ();
+    type Error = RawError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), RawError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), RawError> {
+        Ok(())
+    }
+}
+
+struct RawDeserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> RawDeserializer<'de> {
+    fn take(&mut self, len: usize) -> Result<&'de [u8], RawError> {
+        if self.input.len() < len {
+            return Err(RawError(format!("expected {} more bytes, {} left", len, self.input.len())));
+        }
+        let (head, tail) = self.input.split_at(len);
+        self.input = tail;
+        Ok(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], RawError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn take_rest(&mut self) -> &'de [u8] {
+        let rest = self.input;
+        self.input = &[];
+        rest
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut RawDeserializer<'de> {
+    type Error = RawError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, RawError> {
+        unsupported("self-describing data")
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        match self.take(1)?[0] {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            other => Err(RawError(format!("invalid bool byte {}", other))),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_i8(i8::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_i16(i16::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_i32(i32::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_i64(i64::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_i128(i128::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_u8(self.take(1)?[0])
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_u16(u16::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_u32(u32::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_u64(u64::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_u128(u128::from_le_bytes(self.take_array()?))
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        let rest = self.take_rest();
+        let value = std::str::from_utf8(rest).map_err(|err| RawError(err.to_string()))?;
+        visitor.visit_borrowed_str(value)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_borrowed_bytes(self.take_rest())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        if self.input.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_seq(RawSeq { de: self, left: None })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, RawError> {
+        visitor.visit_seq(RawSeq { de: self, left: Some(len) })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, len: usize, visitor: V) -> Result<V::Value, RawError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, RawError> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        f32 f64 char map enum identifier ignored_any
+    }
+}
+
+// `left: None` reads elements until the input is used up
+struct RawSeq<'a, 'de> {
+    de: &'a mut RawDeserializer<'de>,
+    left: Option<usize>,
+}
+
+impl<'de> SeqAccess<'de> for RawSeq<'_, 'de> {
+    type Error = RawError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, RawError> {
+        match &mut self.left {
+            Some(0) => return Ok(None),
+            Some(left) => *left -= 1,
+            None if self.de.input.is_empty() => return Ok(None),
+            None => {}
+        }
+        let before = self.de.input.len();
+        let value = seed.deserialize(&mut *self.de)?;
+        if self.left.is_none() && self.de.input.len() == before {
+            return Err(RawError("zero sized element in a sequence of unknown length".to_string()));
+        }
+        Ok(Some(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.left
+    }
+}
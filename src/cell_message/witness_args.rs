@@ -0,0 +1,137 @@
+use ckb_testtool::ckb_types::bytes::Bytes;
+use ckb_testtool::ckb_types::packed;
+use ckb_testtool::ckb_types::prelude::{Builder, Entity, Pack};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_molecule::{from_slice, to_vec};
+
+// molecule layout of WitnessArgs:
+// table WitnessArgs { lock: BytesOpt, input_type: BytesOpt, output_type: BytesOpt }
+#[derive(Serialize, Deserialize)]
+struct WitnessArgsLayout {
+    lock: Option<Vec<u8>>,
+    input_type: Option<Vec<u8>>,
+    output_type: Option<Vec<u8>>,
+}
+
+///
+/// Molecule `WitnessArgs` with typed payloads.
+///
+/// Each payload is encoded with molecule struct encoding and stored in the
+/// matching `BytesOpt` field. `WitnessArgs` itself is a molecule table, so the
+/// cell's `witness` encoding must be `Encoding::Table`.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct WitnessArgs<L, I, O> {
+    pub lock: Option<L>,
+    pub input_type: Option<I>,
+    pub output_type: Option<O>,
+}
+
+/// `WitnessArgs` that only carries a lock payload
+pub type LockWitnessArgs<L> = WitnessArgs<L, Vec<u8>, Vec<u8>>;
+
+/// `WitnessArgs` with all fields empty, serializes to `[16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0]`
+pub type EmptyWitnessArgs = WitnessArgs<Vec<u8>, Vec<u8>, Vec<u8>>;
+
+/// length of an `EmptyWitnessArgs`: the full size and the offsets of its three fields
+pub const EMPTY_WITNESS_ARGS_LEN: usize = 16;
+
+impl EmptyWitnessArgs {
+    /// the table bytes, an error if a field is set
+    pub fn to_bytes(&self) -> Result<[u8; EMPTY_WITNESS_ARGS_LEN], String> {
+        let bytes = to_vec(self, false).map_err(|err| err.to_string())?;
+        bytes.try_into().map_err(|bytes: Vec<u8>| format!("WitnessArgs of {} bytes is not empty", bytes.len()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let witness_args: EmptyWitnessArgs = from_slice(bytes, false).map_err(|err| err.to_string())?;
+        witness_args.to_bytes()?;
+        Ok(witness_args)
+    }
+}
+
+///
+/// `#[serde(with = "empty_witness_args_serde")]` lays an `EmptyWitnessArgs` out as its 16 table bytes
+/// inside a molecule struct, for the witnesses a contract reads right after an empty `WitnessArgs`:
+/// ```ignore
+/// pub struct FundingWitness {
+///     #[serde(with = "empty_witness_args_serde")]
+///     pub witness_args: EmptyWitnessArgs,
+///     pub version: u64,
+///     // ...
+/// }
+/// ```
+pub mod empty_witness_args_serde {
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+    use super::{EmptyWitnessArgs, EMPTY_WITNESS_ARGS_LEN};
+
+    pub fn serialize<S: Serializer>(witness_args: &EmptyWitnessArgs, serializer: S) -> Result<S::Ok, S::Error> {
+        witness_args.to_bytes().map_err(ser::Error::custom)?.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EmptyWitnessArgs, D::Error> {
+        let bytes = <[u8; EMPTY_WITNESS_ARGS_LEN]>::deserialize(deserializer)?;
+        EmptyWitnessArgs::from_bytes(&bytes).map_err(de::Error::custom)
+    }
+}
+
+impl<L, I, O> WitnessArgs<L, I, O> {
+    pub fn new(lock: Option<L>, input_type: Option<I>, output_type: Option<O>) -> Self {
+        WitnessArgs { lock, input_type, output_type }
+    }
+}
+
+impl<L: Serialize, I: Serialize, O: Serialize> WitnessArgs<L, I, O> {
+    ///
+    /// the same value built with ckb-types, handy to cross check an encoding
+    pub fn to_packed(&self) -> packed::WitnessArgs {
+        let lock = encode_payload::<_, serde_molecule::Error>(&self.lock).unwrap();
+        let input_type = encode_payload::<_, serde_molecule::Error>(&self.input_type).unwrap();
+        let output_type = encode_payload::<_, serde_molecule::Error>(&self.output_type).unwrap();
+        packed::WitnessArgs::new_builder()
+            .lock(lock.map(Bytes::from).pack())
+            .input_type(input_type.map(Bytes::from).pack())
+            .output_type(output_type.map(Bytes::from).pack())
+            .build()
+    }
+}
+
+impl<L: Serialize, I: Serialize, O: Serialize> Serialize for WitnessArgs<L, I, O> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let layout = WitnessArgsLayout {
+            lock: encode_payload(&self.lock)?,
+            input_type: encode_payload(&self.input_type)?,
+            output_type: encode_payload(&self.output_type)?,
+        };
+        layout.serialize(serializer)
+    }
+}
+
+impl<'de, L: DeserializeOwned, I: DeserializeOwned, O: DeserializeOwned> Deserialize<'de> for WitnessArgs<L, I, O> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let layout = WitnessArgsLayout::deserialize(deserializer)?;
+        Ok(WitnessArgs {
+            lock: decode_payload(layout.lock)?,
+            input_type: decode_payload(layout.input_type)?,
+            output_type: decode_payload(layout.output_type)?,
+        })
+    }
+}
+
+fn encode_payload<T: Serialize, E: serde::ser::Error>(payload: &Option<T>) -> Result<Option<Vec<u8>>, E> {
+    payload.as_ref()
+        .map(|payload| to_vec(payload, true).map_err(E::custom))
+        .transpose()
+}
+
+fn decode_payload<T: DeserializeOwned, E: serde::de::Error>(payload: Option<Vec<u8>>) -> Result<Option<T>, E> {
+    payload
+        .map(|bytes| from_slice(&bytes, true).map_err(E::custom))
+        .transpose()
+}
+
+///
+/// parse raw witness bytes as molecule `WitnessArgs`
+pub fn parse_witness_args(witness: &[u8]) -> Option<packed::WitnessArgs> {
+    packed::WitnessArgs::from_slice(witness).ok()
+}
@@ -1,344 +1,156 @@
-use serde::{Deserialize, Serialize};
-use crate::cell_message::cell::MoleculeStructFlag;
-use serde_molecule::{dynvec_serde, big_array_serde, struct_serde};
-use crate::{impl_cell_methods, impl_cell_methods_without_import};
+use std::fmt;
+use serde::de::{self, Visitor};
+use serde::ser;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::cell_message::cell::Cell;
+use crate::cell_message::encoding::Encoding;
+use crate::cell_message::witness_args::{EmptyWitnessArgs, EMPTY_WITNESS_ARGS_LEN};
+use crate::fiber::commitment::{HtlcType, UnlockType};
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct PendingHtlc {
-    pub htlc_type: u8,
-    pub payment_amount: u128, // u128 in little endian
+    pub htlc_type: HtlcType,
+    pub payment_amount: u128,
     pub payment_hash: [u8; 20],
     pub remote_htlc_pubkey_hash: [u8; 20],
     pub local_htlc_pubkey_hash: [u8; 20],
-    pub htlc_expiry: u64, // 8 bytes, u64 in little endian, must be an absolute timestamp
-}
-
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+    /// must be an absolute timestamp
+    pub htlc_expiry: u64,
+}
+
+///
+/// A commitment-lock witness with any number of pending htlcs and an optional preimage.
+///
+/// The contract reads it after an empty `WitnessArgs`, with no molecule header of its own:
+/// empty witness args (16) | local_delay_epoch (8) | local_delay_pubkey_hash (20) | revocation_pubkey_hash (20)
+/// | pending htlcs (85 each) | unlock_type (1) | signature (65) | preimage (32, optional),
+/// so it serializes to these bytes, to be used with the raw encoding.
+#[derive(Clone, PartialEq, Debug)]
 pub struct CommitmentWitness {
-    pub empty_witness_args: [u8; 16],
+    pub witness_args: EmptyWitnessArgs,
     pub local_delay_epoch: u64,
     pub local_delay_pubkey_hash: [u8; 20],
     pub revocation_pubkey_hash: [u8; 20],
-
-    #[serde(with = "dynvec_serde")]
     pub pending_htlc: Vec<PendingHtlc>,
-    pub unlock_type: u8,
-    #[serde(with = "big_array_serde")]
+    pub unlock_type: UnlockType,
     pub signature: [u8; 65],
-    #[serde(with = "struct_serde")]
     pub preimage: Option<[u8; 32]>,
 }
 
-
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct CommitmentWitnessNoHtlcAndPreImage {
-    pub empty_witness_args: [u8; 16],
-    pub local_delay_epoch: u64,
-    pub local_delay_pubkey_hash: [u8; 20],
-    pub revocation_pubkey_hash: [u8; 20],
-
-    // #[serde(with = "dynvec_serde")]
-    // pub pending_htlc: Vec<PendingHtlc>,
-    pub unlock_type: u8,
-    #[serde(with = "big_array_serde")]
-    pub signature: [u8; 65],
-    // #[serde(with = "struct_serde")]
-    // pub preimage:Option<[u8;32]>
-}
-
-
-pub struct CommitmentCellNoHtlcAndPreImage {
-    pub lock_arg: [u8; 20],
-    pub type_arg: Option<u8>,
-    pub data: u8,
-    pub witness: Option<CommitmentWitnessNoHtlcAndPreImage>,
-    pub struct_flag: MoleculeStructFlag,
-}
-
-impl CommitmentCellNoHtlcAndPreImage {
-    pub(crate) fn default() -> Self {
-        CommitmentCellNoHtlcAndPreImage {
-            lock_arg: [0; 20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
-        }
-    }
-}
-
-
-pub struct CommitmentCell {
-    pub lock_arg: [u8; 20],
-    pub type_arg: Option<u8>,
-    pub data: u8,
-    pub witness: Option<CommitmentWitness>,
-    pub struct_flag: MoleculeStructFlag,
-}
-
-impl CommitmentCell {
-    pub(crate) fn default() -> Self {
-        CommitmentCell {
-            lock_arg: [0; 20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
-        }
+impl CommitmentWitness {
+    // offsets of the fields before the pending htlcs, in the order `to_bytes` writes them
+    const LOCAL_DELAY_EPOCH_AT: usize = EMPTY_WITNESS_ARGS_LEN;
+    const LOCAL_DELAY_PUBKEY_HASH_AT: usize = Self::LOCAL_DELAY_EPOCH_AT + 8;
+    const REVOCATION_PUBKEY_HASH_AT: usize = Self::LOCAL_DELAY_PUBKEY_HASH_AT + 20;
+    /// length of the witness before the pending htlcs
+    pub const PREFIX_LEN: usize = Self::REVOCATION_PUBKEY_HASH_AT + 20;
+    pub const PENDING_HTLC_LEN: usize = 85;
+    /// length of the unlock type and the signature, after the pending htlcs
+    pub const SUFFIX_LEN: usize = 1 + 65;
+    pub const PREIMAGE_LEN: usize = 32;
+
+    /// length of the witness with `pending_htlcs` htlcs
+    pub fn len_with(pending_htlcs: usize, preimage: bool) -> usize {
+        Self::PREFIX_LEN + pending_htlcs * Self::PENDING_HTLC_LEN + Self::SUFFIX_LEN + if preimage { Self::PREIMAGE_LEN } else { 0 }
     }
-}
 
-
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct CommitmentPendinghtlc1Witness {
-    pub empty_witness_args: [u8; 16],
-    pub local_delay_epoch: u64,
-    pub local_delay_pubkey_hash: [u8; 20],
-    pub revocation_pubkey_hash: [u8; 20],
-
-    #[serde(with = "struct_serde")]
-    pub pending_htlc1: PendingHtlc,
-    pub unlock_type: u8,
-    #[serde(with = "big_array_serde")]
-    pub signature: [u8; 65],
-    // #[serde(with = "struct_serde")]
-    // pub preimage: Option<[u8; 32]>,
-}
-
-pub struct CommitmentHTCL1Cell {
-    pub lock_arg: [u8; 20],
-    pub type_arg: Option<[u8; 32]>,
-    pub data: u128,
-    pub witness: Option<CommitmentPendinghtlc1Witness>,
-    pub struct_flag: MoleculeStructFlag,
-}
-
-impl CommitmentHTCL1Cell {
-    pub(crate) fn default() -> Self {
-        CommitmentHTCL1Cell {
-            lock_arg: [0; 20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
+    /// fails on an `unlock_type` with no byte, see `UnlockType`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = [
+            self.witness_args.to_bytes().expect("the witness args of a commitment witness are empty").to_vec(),
+            self.local_delay_epoch.to_le_bytes().to_vec(),
+            self.local_delay_pubkey_hash.to_vec(),
+            self.revocation_pubkey_hash.to_vec(),
+        ].concat();
+        for htlc in &self.pending_htlc {
+            bytes.extend(Encoding::Raw.encode(htlc).expect("encode pending htlc"));
         }
-    }
-}
-
-
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct CommitmentPendinghtlc2Witness {
-    pub empty_witness_args: [u8; 16],
-    pub local_delay_epoch: u64,
-    pub local_delay_pubkey_hash: [u8; 20],
-    pub revocation_pubkey_hash: [u8; 20],
-
-    #[serde(with = "struct_serde")]
-    pub pending_htlc1: PendingHtlc,
-
-    #[serde(with = "struct_serde")]
-    pub pending_htlc2: PendingHtlc,
-
-    pub unlock_type: u8,
-    #[serde(with = "big_array_serde")]
-    pub signature: [u8; 65],
-    // #[serde(with = "struct_serde")]
-    // pub preimage: Option<[u8; 32]>,
-}
-
-pub struct CommitmentHTCL2Cell {
-    pub lock_arg: [u8; 20],
-    pub type_arg: Option<u8>,
-    pub data: u8,
-    pub witness: Option<CommitmentPendinghtlc2Witness>,
-    pub struct_flag: MoleculeStructFlag,
-}
-
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct CommitmentPendinghtlc2WithPriImageWitness {
-    pub empty_witness_args: [u8; 16],
-    pub local_delay_epoch: u64,
-    pub local_delay_pubkey_hash: [u8; 20],
-    pub revocation_pubkey_hash: [u8; 20],
-
-    #[serde(with = "struct_serde")]
-    pub pending_htlc1: PendingHtlc,
-
-    #[serde(with = "struct_serde")]
-    pub pending_htlc2: PendingHtlc,
-
-    pub unlock_type: u8,
-    #[serde(with = "big_array_serde")]
-    pub signature: [u8; 65],
-    // #[serde(with = "struct_serde")]
-    pub preimage: [u8; 32],
-}
-
-
-pub struct CommitmentHTCL2WithPriImageAndUDTCell {
-    pub lock_arg: [u8; 20],
-    pub type_arg: Option<[u8; 32]>,
-    pub data: u128,
-    pub witness: Option<CommitmentPendinghtlc2WithPriImageWitness>,
-    pub struct_flag: MoleculeStructFlag,
-}
-
-
-impl CommitmentHTCL2WithPriImageAndUDTCell {
-    pub(crate) fn default() -> Self {
-        CommitmentHTCL2WithPriImageAndUDTCell {
-            lock_arg: [0; 20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
+        bytes.push(u8::try_from(self.unlock_type)?);
+        bytes.extend(self.signature);
+        if let Some(preimage) = self.preimage {
+            bytes.extend(preimage);
         }
+        Ok(bytes)
     }
-}
-
 
-impl CommitmentHTCL2Cell {
-    pub(crate) fn default() -> Self {
-        CommitmentHTCL2Cell {
-            lock_arg: [0; 20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
-        }
+    ///
+    /// The htlc count and whether there is a preimage follow from the length, 32 bytes not being a multiple of 85.
+    /// A 0xFF unlock type decodes to `UnlockType::Revocation`, the witness does not tell it from a local delay unlock.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let body = bytes.len().checked_sub(Self::PREFIX_LEN + Self::SUFFIX_LEN)
+            .ok_or_else(|| format!("a commitment witness is at least {} bytes, got {}", Self::len_with(0, false), bytes.len()))?;
+        let (pending_htlcs, preimage) = match body % Self::PENDING_HTLC_LEN {
+            0 => (body / Self::PENDING_HTLC_LEN, false),
+            Self::PREIMAGE_LEN => (body / Self::PENDING_HTLC_LEN, true),
+            _ => return Err(format!("{} bytes is not the length of a commitment witness", bytes.len())),
+        };
+
+        let htlcs_end = Self::PREFIX_LEN + pending_htlcs * Self::PENDING_HTLC_LEN;
+        let pending_htlc = bytes[Self::PREFIX_LEN..htlcs_end]
+            .chunks(Self::PENDING_HTLC_LEN)
+            .map(|htlc| Encoding::Raw.decode(htlc))
+            .collect::<Result<Vec<PendingHtlc>, String>>()?;
+        Ok(CommitmentWitness {
+            witness_args: EmptyWitnessArgs::from_bytes(&bytes[0..EMPTY_WITNESS_ARGS_LEN])?,
+            local_delay_epoch: u64::from_le_bytes(bytes[Self::LOCAL_DELAY_EPOCH_AT..Self::LOCAL_DELAY_PUBKEY_HASH_AT].try_into().unwrap()),
+            local_delay_pubkey_hash: bytes[Self::LOCAL_DELAY_PUBKEY_HASH_AT..Self::REVOCATION_PUBKEY_HASH_AT].try_into().unwrap(),
+            revocation_pubkey_hash: bytes[Self::REVOCATION_PUBKEY_HASH_AT..Self::PREFIX_LEN].try_into().unwrap(),
+            pending_htlc,
+            unlock_type: UnlockType::from(bytes[htlcs_end]),
+            signature: bytes[htlcs_end + 1..htlcs_end + Self::SUFFIX_LEN].try_into().unwrap(),
+            preimage: preimage.then(|| bytes[htlcs_end + Self::SUFFIX_LEN..].try_into().unwrap()),
+        })
     }
 }
 
-
-pub struct CommitmentArgErrCell {
-    pub lock_arg: [u8; 22],
-    pub type_arg: Option<u8>,
-    pub data: u8,
-    pub witness: Option<CommitmentWitness>,
-    pub struct_flag: MoleculeStructFlag,
-}
-
-impl CommitmentArgErrCell {
-    pub(crate) fn default() -> Self {
-        CommitmentArgErrCell {
-            lock_arg: [0; 22],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
-        }
+impl Serialize for CommitmentWitness {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes().map_err(ser::Error::custom)?)
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct CommitmentMinErrLenWitness {
-    pub empty_witness_args: [u8; 16],
-    pub local_delay_epoch: u64,
-    pub local_delay_pubkey_hash: [u8; 20],
-    pub revocation_pubkey_hash: [u8; 20],
+impl<'de> Deserialize<'de> for CommitmentWitness {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct WitnessVisitor;
 
-    // pub unlock_type: u8,
-    #[serde(with = "big_array_serde")]
-    pub signature: [u8; 65],
-}
+        impl Visitor<'_> for WitnessVisitor {
+            type Value = CommitmentWitness;
 
-pub struct CommitmentMinWitnessLenErrCell {
-    pub lock_arg: [u8; 20],
-    pub type_arg: Option<u8>,
-    pub data: u8,
-    pub witness: Option<CommitmentMinErrLenWitness>,
-    pub struct_flag: MoleculeStructFlag,
-}
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "the bytes of a commitment witness")
+            }
 
-impl CommitmentMinWitnessLenErrCell {
-    pub(crate) fn default() -> Self {
-        CommitmentMinWitnessLenErrCell {
-            lock_arg: [0; 20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<CommitmentWitness, E> {
+                CommitmentWitness::from_bytes(bytes).map_err(E::custom)
+            }
         }
-    }
-}
-
-
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct CommitmentMaxErrLenWitness {
-    pub empty_witness_args: [u8; 16],
-    pub local_delay_epoch: u64,
-    pub local_delay_pubkey_hash: [u8; 20],
-    pub revocation_pubkey_hash: [u8; 20],
-
-    pub unlock_type: u8,
-    #[serde(with = "big_array_serde")]
-    pub signature: [u8; 65],
-    pub err: [u8; 5],
-}
-
-pub struct CommitmentMaxWitnessLenErrCell {
-    pub lock_arg: [u8; 20],
-    pub type_arg: Option<u8>,
-    pub data: u8,
-    pub witness: Option<CommitmentMaxErrLenWitness>,
-    pub struct_flag: MoleculeStructFlag,
-}
 
-impl CommitmentMaxWitnessLenErrCell {
-    pub(crate) fn default() -> Self {
-        CommitmentMaxWitnessLenErrCell {
-            lock_arg: [0; 20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
-        }
+        deserializer.deserialize_bytes(WitnessVisitor)
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct CommitmentPendinghtlc1WithPreimageWitness {
-    pub empty_witness_args: [u8; 16],
-    pub local_delay_epoch: u64,
-    pub local_delay_pubkey_hash: [u8; 20],
-    pub revocation_pubkey_hash: [u8; 20],
-
-    #[serde(with = "struct_serde")]
-    pub pending_htlc1: PendingHtlc,
-    pub unlock_type: u8,
-    #[serde(with = "big_array_serde")]
-    pub signature: [u8; 65],
-    // #[serde(with = "struct_serde")]
-    pub preimage: [u8; 32],
-}
-
-pub struct CommitmentHTCL1WithPreimageAndUDTCell {
+/// a commitment-lock cell, whose witness holds any number of htlcs
+#[derive(Cell, Clone, PartialEq, Debug, Default)]
+pub struct CommitmentLockCell<T, D> {
     pub lock_arg: [u8; 20],
-    pub type_arg: Option<[u8; 32]>,
-    pub data: u128,
-    pub witness: Option<CommitmentPendinghtlc1WithPreimageWitness>,
-    pub struct_flag: MoleculeStructFlag,
+    pub type_arg: Option<T>,
+    pub data: D,
+    #[cell(encoding = "raw")]
+    pub witness: Option<CommitmentWitness>,
 }
 
-impl CommitmentHTCL1WithPreimageAndUDTCell {
-    pub(crate) fn default() -> Self {
-        CommitmentHTCL1WithPreimageAndUDTCell {
-            lock_arg: [0; 20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
+impl<T, D> CommitmentLockCell<T, D> {
+    pub fn new(lock_arg: [u8; 20], type_arg: Option<T>, data: D, witness: Option<CommitmentWitness>) -> Self {
+        CommitmentLockCell {
+            lock_arg,
+            type_arg,
+            data,
+            witness,
         }
     }
 }
 
+pub type CommitmentCell = CommitmentLockCell<u8, u8>;
 
-impl_cell_methods!(CommitmentCellNoHtlcAndPreImage);
-impl_cell_methods_without_import!(CommitmentCell);
-impl_cell_methods_without_import!(CommitmentArgErrCell);
-impl_cell_methods_without_import!(CommitmentMinWitnessLenErrCell);
-impl_cell_methods_without_import!(CommitmentMaxWitnessLenErrCell);
-impl_cell_methods_without_import!(CommitmentHTCL1Cell);
-impl_cell_methods_without_import!(CommitmentHTCL2Cell);
-impl_cell_methods_without_import!(CommitmentHTCL2WithPriImageAndUDTCell);
-impl_cell_methods_without_import!(CommitmentHTCL1WithPreimageAndUDTCell);
\ No newline at end of file
+/// a commitment-lock cell of a UDT: type args and amount
+pub type CommitmentUdtCell = CommitmentLockCell<[u8; 32], u128>;
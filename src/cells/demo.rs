@@ -1,12 +1,12 @@
-use crate::cell_message::cell::MoleculeStructFlag;
-use crate::impl_cell_methods;
+use crate::cell_message::cell::{Cell, FieldEncodings};
 
+#[derive(Cell)]
 pub struct Demo {
     pub lock_arg: u8,
     pub type_arg: Option<u8>,
     pub data: u8,
     pub witness: Option<u8>,
-    pub struct_flag: MoleculeStructFlag,
+    pub encodings: FieldEncodings,
 }
 
 impl Demo {
@@ -16,7 +16,7 @@ impl Demo {
             type_arg: None,
             data: 1,
             witness: None,
-            struct_flag: MoleculeStructFlag::default(),
+            encodings: FieldEncodings::default(),
         };
     }
     pub(crate) fn new() -> Self {
@@ -25,12 +25,7 @@ impl Demo {
             type_arg: None,
             data: 1,
             witness: None,
-            struct_flag: MoleculeStructFlag::default(),
+            encodings: FieldEncodings::default(),
         };
     }
 }
-
-
-
-
-impl_cell_methods!(Demo);
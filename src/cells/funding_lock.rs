@@ -1,43 +1,20 @@
 use serde::{Deserialize, Serialize};
-use crate::cell_message::cell::MoleculeStructFlag;
+use crate::cell_message::molecule_cell::MoleculeCell;
+use crate::cell_message::witness_args::{empty_witness_args_serde, EmptyWitnessArgs};
 use serde_molecule::big_array_serde;
-use crate::cells::xudt_data::{XUDTData, XUDTDataCell, XUDTWitness};
-use crate::impl_cell_methods;
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct FundingWitness {
-    pub empty_witness_args: [u8; 16],
+    /// the contract reads the payload after an empty `WitnessArgs`
+    #[serde(with = "empty_witness_args_serde")]
+    pub witness_args: EmptyWitnessArgs,
     pub version: u64,
     #[serde(with = "big_array_serde")]
     pub funding_out_point: [u8; 36],
+    /// x-only aggregated public key
     pub pubkey: [u8; 32],
     #[serde(with = "big_array_serde")]
     pub signature: [u8; 64],
 }
 
-
-pub struct FundingCell {
-    pub lock_arg: [u8;20],
-    pub type_arg: Option<u8>,
-    pub data: u8,
-    pub witness: Option<FundingWitness>,
-    pub struct_flag: MoleculeStructFlag,
-}
-
-
-
-impl FundingCell {
-    pub(crate) fn default() -> Self {
-        return FundingCell {
-            lock_arg: [0;20],
-            type_arg: None,
-            data: 0,
-            witness: None,
-            struct_flag: MoleculeStructFlag::default(),
-        };
-    }
-}
-
-
-
-impl_cell_methods!(FundingCell);
+pub type FundingCell = MoleculeCell<[u8; 20], u8, u8, FundingWitness>;
@@ -1,8 +1,5 @@
-pub mod macros;
 pub mod demo;
 pub mod xudt_data;
 
 pub mod funding_lock;
-pub mod funding_lock_err;
 pub mod commitment_lock;
-
@@ -1,15 +1,16 @@
-use crate::cell_message::cell::MoleculeStructFlag;
+use crate::cell_message::cell::{Cell, FieldEncodings};
+use crate::cell_message::witness_args::EmptyWitnessArgs;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use crate::impl_cell_methods;
 
 #[derive(PartialEq)]
 #[derive(Debug)]
+#[derive(Cell)]
 pub struct XUDTDataCell {
     pub lock_arg: u8,
     pub type_arg: Option<[u8; 32]>,
     pub data: XUDTData,
     pub witness: Option<XUDTWitness>,
-    pub struct_flag: MoleculeStructFlag,
+    pub encodings: FieldEncodings,
 }
 
 
@@ -18,12 +19,8 @@ pub struct XUDTDataCell {
 pub struct XUDTData {
     pub amount: u128,
 }
-const EMPTY_WITNESS_ARGS: [u8; 16] = [16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0];
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
-pub struct XUDTWitness {
-    pub empty_witness_args: [u8; 16],
-}
+pub type XUDTWitness = EmptyWitnessArgs;
 
 impl XUDTDataCell {
     pub(crate) fn default() -> Self {
@@ -32,12 +29,7 @@ impl XUDTDataCell {
             type_arg: None,
             data: XUDTData { amount: 0 },
             witness: None,
-            struct_flag: MoleculeStructFlag {
-                lock_arg: true,
-                type_arg: true,
-                data: true,
-                witness: true,
-            },
+            encodings: FieldEncodings::with_witness_args(),
         };
     }
 
@@ -46,18 +38,8 @@ impl XUDTDataCell {
             lock_arg: 0,
             type_arg: Some(type_arg),
             data: data,
-            witness: Some(XUDTWitness {
-                empty_witness_args: EMPTY_WITNESS_ARGS
-            }),
-            struct_flag: MoleculeStructFlag {
-                lock_arg: true,
-                type_arg: true,
-                data: true,
-                witness: true,
-            },
+            witness: Some(XUDTWitness::default()),
+            encodings: FieldEncodings::with_witness_args(),
         };
     }
 }
-
-
-impl_cell_methods!(XUDTDataCell);
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use ckb_testtool::ckb_types::core::{EpochNumberWithFraction, HeaderBuilder, HeaderView, TransactionView};
+use ckb_testtool::ckb_types::packed::OutPoint;
+use ckb_testtool::ckb_types::prelude::{Pack, Unpack};
+use ckb_testtool::context::Context;
+use crate::chain::since::{Since, SinceMetric};
+
+/// 2024-04-01 00:00:00 UTC, in seconds
+pub const GENESIS_TIMESTAMP: u64 = 1711929600;
+pub const DEFAULT_EPOCH_LENGTH: u64 = 1800;
+/// seconds between two blocks
+pub const DEFAULT_BLOCK_INTERVAL: u64 = 8;
+
+///
+/// A simulated chain tip. Blocks come at a fixed interval and epochs have a fixed length,
+/// every header is inserted into the `Context` so scripts can load it.
+///
+/// The cells created on `ContractUtil` are committed in the tip block, which is what
+/// relative since values count from:
+///
+/// ```ignore
+/// let tx = ct.tx().input_with_since(&commitment_contract, None, &cell, Since::relative_epoch(10, 1, 2), Capacity::shannons(1000)).build();
+/// assert!(ct.check_since(&tx).is_err());
+/// ct.advance_epochs(10, 1, 2);
+/// ct.check_since(&tx).unwrap();
+/// ```
+pub struct ChainClock {
+    tip: HeaderView,
+    epoch_length: u64,
+    block_interval: u64,
+    // the block each cell was committed in
+    committed: HashMap<OutPoint, HeaderView>,
+}
+
+impl Default for ChainClock {
+    fn default() -> Self {
+        Self::new(DEFAULT_EPOCH_LENGTH, DEFAULT_BLOCK_INTERVAL)
+    }
+}
+
+impl ChainClock {
+    /// a chain at its genesis block, `GENESIS_TIMESTAMP`
+    pub fn new(epoch_length: u64, block_interval: u64) -> Self {
+        assert!(epoch_length > 0, "an epoch has at least one block");
+        let genesis = HeaderBuilder::default()
+            .number(0u64.pack())
+            .epoch(EpochNumberWithFraction::new(0, 0, epoch_length).full_value().pack())
+            .timestamp((GENESIS_TIMESTAMP * 1000).pack())
+            .build();
+        ChainClock {
+            tip: genesis,
+            epoch_length,
+            block_interval,
+            committed: HashMap::new(),
+        }
+    }
+
+    pub fn tip(&self) -> &HeaderView {
+        &self.tip
+    }
+
+    /// timestamp of the tip in seconds, what since timestamps are compared with
+    pub fn timestamp(&self) -> u64 {
+        self.tip.timestamp() / 1000
+    }
+
+    pub fn advance_blocks(&mut self, context: &mut Context, blocks: u64) -> HeaderView {
+        let number = self.tip.number() + blocks;
+        self.tip = HeaderBuilder::default()
+            .parent_hash(self.tip.hash())
+            .number(number.pack())
+            .epoch(EpochNumberWithFraction::new(number / self.epoch_length, number % self.epoch_length, self.epoch_length).full_value().pack())
+            .timestamp((self.tip.timestamp() + blocks * self.block_interval * 1000).pack())
+            .build();
+        context.insert_header(self.tip.clone());
+        self.tip.clone()
+    }
+
+    /// advance by at least `number` epochs and `index / length` of an epoch
+    pub fn advance_epochs(&mut self, context: &mut Context, number: u64, index: u64, length: u64) -> HeaderView {
+        let (fraction, length) = epoch_fraction(number, index, length);
+        let blocks = (fraction * self.epoch_length as u128).div_ceil(length);
+        self.advance_blocks(context, blocks as u64)
+    }
+
+    /// advance by at least `seconds`
+    pub fn advance_seconds(&mut self, context: &mut Context, seconds: u64) -> HeaderView {
+        self.advance_blocks(context, seconds.div_ceil(self.block_interval))
+    }
+
+    /// commit `out_point` in the tip block
+    pub fn commit(&mut self, context: &mut Context, out_point: &OutPoint) {
+        context.link_cell_with_block(out_point.clone(), self.tip.hash(), 0);
+        self.committed.insert(out_point.clone(), self.tip.clone());
+    }
+
+    /// the block `out_point` was committed in
+    pub fn committed(&self, out_point: &OutPoint) -> Option<&HeaderView> {
+        self.committed.get(out_point)
+    }
+
+    ///
+    /// Whether `since` is mature at the tip for a cell committed in `committed`.
+    /// The tip timestamp stands in for the median time of the past blocks.
+    pub fn is_mature(&self, since: Since, committed: &HeaderView) -> Result<bool, String> {
+        since.check()?;
+        if since == Since::NONE {
+            return Ok(true);
+        }
+        let relative = since.is_relative();
+        let mature = match since.metric()? {
+            SinceMetric::BlockNumber(number) => {
+                let base = if relative { committed.number() } else { 0 };
+                self.tip.number() >= base + number
+            }
+            SinceMetric::Epoch { number, index, length } => {
+                let (mut fraction, mut length) = epoch_fraction(number, index, length);
+                if relative {
+                    let epoch = committed.epoch();
+                    let (base, base_length) = epoch_fraction(epoch.number(), epoch.index(), epoch.length());
+                    fraction = fraction * base_length + base * length;
+                    length *= base_length;
+                }
+                let tip = self.tip.epoch();
+                let (tip_fraction, tip_length) = epoch_fraction(tip.number(), tip.index(), tip.length());
+                tip_fraction * length >= fraction * tip_length
+            }
+            SinceMetric::Timestamp(seconds) => {
+                let base = if relative { committed.timestamp() / 1000 } else { 0 };
+                self.timestamp() >= base + seconds
+            }
+        };
+        Ok(mature)
+    }
+
+    /// the since of every input is mature at the tip, as the chain checks before running any script
+    pub fn check_tx(&self, tx: &TransactionView) -> Result<(), String> {
+        for (index, input) in tx.inputs().into_iter().enumerate() {
+            let since = Since::raw(input.since().unpack());
+            if since == Since::NONE {
+                continue;
+            }
+            let committed = self.committed(&input.previous_output())
+                .ok_or_else(|| format!("input {} was not committed on the clock", index))?;
+            if !self.is_mature(since, committed).map_err(|err| format!("input {}: {}", index, err))? {
+                return Err(format!(
+                    "input {} {} is not mature at block {} (epoch {} {}/{}, timestamp {})",
+                    index, since, self.tip.number(), self.tip.epoch().number(), self.tip.epoch().index(), self.tip.epoch().length(), self.timestamp(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// `number + index / length` epochs as a fraction, a relative 0/0 being a whole number of epochs
+fn epoch_fraction(number: u64, index: u64, length: u64) -> (u128, u128) {
+    let length = length.max(1) as u128;
+    (number as u128 * length + index as u128, length)
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::fmt;
+use ckb_testtool::ckb_types::core::{Capacity, TransactionView};
+use ckb_testtool::ckb_types::prelude::{Entity, Unpack};
+use ckb_testtool::context::Context;
+
+/// the only transaction version a node accepts
+pub const TX_VERSION: u32 = 0;
+
+/// why a node rejects a transaction before running any of its scripts
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ConsensusError {
+    Version(u32),
+    EmptyInputs,
+    EmptyOutputs,
+    /// `outputs` and `outputs_data` have different lengths
+    OutputsDataLengthMismatch { outputs: usize, outputs_data: usize },
+    DuplicateInput { index: usize },
+    DuplicateCellDep { index: usize },
+    DuplicateHeaderDep { index: usize },
+    /// the input is not a cell of the context
+    UnknownInput { index: usize },
+    /// the output holds less than its lock, type and data occupy, in shannons
+    InsufficientCellCapacity { index: usize, occupied: u64, capacity: u64 },
+    /// the outputs hold more than the inputs, in shannons
+    OutputsExceedInputs { inputs: u64, outputs: u64 },
+    CapacityOverflow,
+}
+
+impl fmt::Display for ConsensusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsensusError::Version(version) => write!(f, "tx version {} is not {}", version, TX_VERSION),
+            ConsensusError::EmptyInputs => write!(f, "tx has no inputs"),
+            ConsensusError::EmptyOutputs => write!(f, "tx has no outputs"),
+            ConsensusError::OutputsDataLengthMismatch { outputs, outputs_data } => {
+                write!(f, "{} outputs but {} outputs data", outputs, outputs_data)
+            }
+            ConsensusError::DuplicateInput { index } => write!(f, "input {} is spent twice", index),
+            ConsensusError::DuplicateCellDep { index } => write!(f, "cell dep {} is duplicated", index),
+            ConsensusError::DuplicateHeaderDep { index } => write!(f, "header dep {} is duplicated", index),
+            ConsensusError::UnknownInput { index } => write!(f, "input {} is not a known cell", index),
+            ConsensusError::InsufficientCellCapacity { index, occupied, capacity } => {
+                write!(f, "output {} holds {} shannons but occupies {}", index, capacity, occupied)
+            }
+            ConsensusError::OutputsExceedInputs { inputs, outputs } => {
+                write!(f, "outputs hold {} shannons but inputs only {}", outputs, inputs)
+            }
+            ConsensusError::CapacityOverflow => write!(f, "capacity overflow"),
+        }
+    }
+}
+
+///
+/// The checks a node runs on a transaction besides its scripts, which `Context::verify_tx` skips:
+/// version, non empty inputs and outputs, one data per output, no duplicate input or dep,
+/// every output holding its occupied capacity and the outputs holding no more than the inputs.
+///
+/// Since maturity and dead cells are left to `ChainClock` and `Ledger`, the DAO withdraw bonus is not counted.
+pub fn check_tx(context: &Context, tx: &TransactionView) -> Result<(), ConsensusError> {
+    let version = tx.version();
+    if version != TX_VERSION {
+        return Err(ConsensusError::Version(version));
+    }
+    if tx.inputs().is_empty() {
+        return Err(ConsensusError::EmptyInputs);
+    }
+    if tx.outputs().is_empty() {
+        return Err(ConsensusError::EmptyOutputs);
+    }
+    if tx.outputs().len() != tx.outputs_data().len() {
+        return Err(ConsensusError::OutputsDataLengthMismatch { outputs: tx.outputs().len(), outputs_data: tx.outputs_data().len() });
+    }
+
+    let mut inputs = HashSet::new();
+    for (index, input) in tx.inputs().into_iter().enumerate() {
+        if !inputs.insert(input.previous_output()) {
+            return Err(ConsensusError::DuplicateInput { index });
+        }
+    }
+    let mut cell_deps = HashSet::new();
+    for (index, cell_dep) in tx.cell_deps().into_iter().enumerate() {
+        if !cell_deps.insert(cell_dep.as_bytes()) {
+            return Err(ConsensusError::DuplicateCellDep { index });
+        }
+    }
+    let mut header_deps = HashSet::new();
+    for (index, header_dep) in tx.header_deps().into_iter().enumerate() {
+        if !header_deps.insert(header_dep.as_bytes()) {
+            return Err(ConsensusError::DuplicateHeaderDep { index });
+        }
+    }
+
+    let mut outputs_capacity = Capacity::zero();
+    for (index, (output, data)) in tx.outputs_with_data_iter().enumerate() {
+        let capacity: Capacity = output.capacity().unpack();
+        let occupied = Capacity::bytes(data.len())
+            .and_then(|data_capacity| output.occupied_capacity(data_capacity))
+            .map_err(|_| ConsensusError::CapacityOverflow)?;
+        if occupied > capacity {
+            return Err(ConsensusError::InsufficientCellCapacity { index, occupied: occupied.as_u64(), capacity: capacity.as_u64() });
+        }
+        outputs_capacity = outputs_capacity.safe_add(capacity).map_err(|_| ConsensusError::CapacityOverflow)?;
+    }
+    let mut inputs_capacity = Capacity::zero();
+    for (index, input) in tx.inputs().into_iter().enumerate() {
+        let (cell, _) = context.get_cell(&input.previous_output()).ok_or(ConsensusError::UnknownInput { index })?;
+        let capacity: Capacity = cell.capacity().unpack();
+        inputs_capacity = inputs_capacity.safe_add(capacity).map_err(|_| ConsensusError::CapacityOverflow)?;
+    }
+    if outputs_capacity > inputs_capacity {
+        return Err(ConsensusError::OutputsExceedInputs { inputs: inputs_capacity.as_u64(), outputs: outputs_capacity.as_u64() });
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use ckb_testtool::ckb_types::core::TransactionView;
+use ckb_testtool::ckb_types::packed::{Byte32, OutPoint};
+use crate::chain::consensus::ConsensusError;
+
+/// why the ledger rejects a transaction
+#[derive(Clone, PartialEq, Debug)]
+pub enum LedgerError {
+    /// the input was spent by an earlier committed transaction
+    DeadInput { index: usize, spent_by: Byte32 },
+    /// the input is spent twice in the transaction
+    DuplicateInput { index: usize },
+    /// the cell dep was spent by an earlier committed transaction
+    DeadCellDep { index: usize, spent_by: Byte32 },
+    /// the since of an input is not mature at the chain tip, see `ChainClock::check_tx`
+    ImmatureInput(String),
+    /// a node rejects the transaction before running its scripts, see `ContractUtil::commit_strict_tx`
+    Consensus(ConsensusError),
+    /// the scripts failed
+    Verification(String),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::DeadInput { index, spent_by } => write!(f, "input {} was spent by tx {}", index, spent_by),
+            LedgerError::DuplicateInput { index } => write!(f, "input {} is spent twice", index),
+            LedgerError::DeadCellDep { index, spent_by } => write!(f, "cell dep {} was spent by tx {}", index, spent_by),
+            LedgerError::ImmatureInput(err) => write!(f, "immature input: {}", err),
+            LedgerError::Consensus(err) => write!(f, "consensus: {}", err),
+            LedgerError::Verification(err) => write!(f, "verification failed: {}", err),
+        }
+    }
+}
+
+///
+/// The live and dead cells on top of a `Context`, which keeps every cell it ever created.
+///
+/// Cells created by `ContractUtil` are live until a transaction spending them is committed with
+/// `ContractUtil::commit_tx`, which also creates the outputs at their real out points, so the next
+/// transaction of a flow can spend them:
+///
+/// ```ignore
+/// let outputs = ct.commit_tx(&funding_tx, MAX_CYCLES).unwrap();
+/// let tx = ct.tx().spend(&outputs[0]).output(&always_success, None, &cell, Capacity::shannons(500)).build();
+/// ct.commit_tx(&tx, MAX_CYCLES).unwrap();
+/// assert!(matches!(ct.commit_tx(&tx, MAX_CYCLES), Err(LedgerError::DeadInput { .. })));
+/// ```
+#[derive(Default)]
+pub struct Ledger {
+    live: HashSet<OutPoint>,
+    // dead cells and the hash of the tx that spent them
+    dead: HashMap<OutPoint, Byte32>,
+}
+
+impl Ledger {
+    pub fn create(&mut self, out_point: &OutPoint) {
+        self.live.insert(out_point.clone());
+    }
+
+    pub fn is_live(&self, out_point: &OutPoint) -> bool {
+        self.live.contains(out_point)
+    }
+
+    /// the hash of the tx that spent `out_point`, if it is dead
+    pub fn spent_by(&self, out_point: &OutPoint) -> Option<&Byte32> {
+        self.dead.get(out_point)
+    }
+
+    /// no input or cell dep of `tx` is dead, and no input is spent twice; cells the ledger never saw pass
+    pub fn check_tx(&self, tx: &TransactionView) -> Result<(), LedgerError> {
+        let mut spent = HashSet::new();
+        for (index, input) in tx.inputs().into_iter().enumerate() {
+            let out_point = input.previous_output();
+            if let Some(spent_by) = self.spent_by(&out_point) {
+                return Err(LedgerError::DeadInput { index, spent_by: spent_by.clone() });
+            }
+            if !spent.insert(out_point) {
+                return Err(LedgerError::DuplicateInput { index });
+            }
+        }
+        for (index, cell_dep) in tx.cell_deps().into_iter().enumerate() {
+            if let Some(spent_by) = self.spent_by(&cell_dep.out_point()) {
+                return Err(LedgerError::DeadCellDep { index, spent_by: spent_by.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    /// spend the inputs of `tx` and create its outputs, returns the out points of the outputs
+    pub fn apply_tx(&mut self, tx: &TransactionView) -> Vec<OutPoint> {
+        for input in tx.inputs().into_iter() {
+            let out_point = input.previous_output();
+            self.live.remove(&out_point);
+            self.dead.insert(out_point, tx.hash());
+        }
+        (0..tx.outputs().len())
+            .map(|index| {
+                let out_point = OutPoint::new(tx.hash(), index as u32);
+                self.create(&out_point);
+                out_point
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,7 @@
+//! Chain state the scripts read besides the transaction: input since values, block headers and live cells,
+//! and the checks a node runs before the scripts.
+
+pub mod since;
+pub mod clock;
+pub mod ledger;
+pub mod consensus;
@@ -0,0 +1,151 @@
+use std::fmt;
+
+const RELATIVE_FLAG: u64 = 1 << 63;
+const METRIC_SHIFT: u32 = 61;
+const METRIC_MASK: u64 = 0b11 << METRIC_SHIFT;
+const RESERVED_MASK: u64 = 0b11111 << 56;
+const VALUE_MASK: u64 = (1 << 56) - 1;
+
+const EPOCH_NUMBER_MAX: u64 = (1 << 24) - 1;
+const EPOCH_FRACTION_MAX: u64 = (1 << 16) - 1;
+
+/// what the value of a since is compared with
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SinceMetric {
+    BlockNumber(u64),
+    /// `number` epochs and `index / length` of an epoch
+    Epoch { number: u64, index: u64, length: u64 },
+    /// median time of the past 37 blocks, in seconds
+    Timestamp(u64),
+}
+
+///
+/// The since of an input, laid out as in CKB RFC 0017: relative flag (bit 63), metric (bits 61-62),
+/// 5 reserved bits and a 56 bits value.
+///
+/// ```ignore
+/// let local_delay = Since::relative_epoch(10, 1, 2);
+/// let expiry = Since::absolute_timestamp(1711976400);
+/// let tx = ct.tx().input_with_since(&commitment_contract, None, &cell, local_delay, Capacity::shannons(1000)).build();
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Since(u64);
+
+impl Since {
+    /// no since, the input can be spent at any time
+    pub const NONE: Since = Since(0);
+
+    /// checks the combination, see `Since::check`
+    pub fn new(relative: bool, metric: SinceMetric) -> Result<Since, String> {
+        let (metric_bits, value) = match metric {
+            SinceMetric::BlockNumber(number) => (0, number),
+            SinceMetric::Epoch { number, index, length } => {
+                if number > EPOCH_NUMBER_MAX || index > EPOCH_FRACTION_MAX || length > EPOCH_FRACTION_MAX {
+                    return Err(format!("epoch {} {}/{} does not fit in 24 and 16 bits", number, index, length));
+                }
+                (1, length << 40 | index << 24 | number)
+            }
+            SinceMetric::Timestamp(seconds) => (2, seconds),
+        };
+        if value > VALUE_MASK {
+            return Err(format!("{:?} does not fit in 56 bits", metric));
+        }
+        let since = Since(if relative { RELATIVE_FLAG } else { 0 } | metric_bits << METRIC_SHIFT | value);
+        since.check()?;
+        Ok(since)
+    }
+
+    pub fn absolute_block_number(number: u64) -> Since {
+        Self::expect(false, SinceMetric::BlockNumber(number))
+    }
+
+    pub fn relative_block_number(blocks: u64) -> Since {
+        Self::expect(true, SinceMetric::BlockNumber(blocks))
+    }
+
+    /// epoch `number` and `index / length` of it
+    pub fn absolute_epoch(number: u64, index: u64, length: u64) -> Since {
+        Self::expect(false, SinceMetric::Epoch { number, index, length })
+    }
+
+    /// `number` epochs and `index / length` of an epoch after the input was committed
+    pub fn relative_epoch(number: u64, index: u64, length: u64) -> Since {
+        Self::expect(true, SinceMetric::Epoch { number, index, length })
+    }
+
+    pub fn absolute_timestamp(seconds: u64) -> Since {
+        Self::expect(false, SinceMetric::Timestamp(seconds))
+    }
+
+    pub fn relative_timestamp(seconds: u64) -> Since {
+        Self::expect(true, SinceMetric::Timestamp(seconds))
+    }
+
+    /// the value as is, not checked, for values the scripts or the chain must reject
+    pub const fn raw(since: u64) -> Since {
+        Since(since)
+    }
+
+    fn expect(relative: bool, metric: SinceMetric) -> Since {
+        Self::new(relative, metric).unwrap_or_else(|err| panic!("invalid since: {}", err))
+    }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_relative(self) -> bool {
+        self.0 & RELATIVE_FLAG != 0
+    }
+
+    pub fn metric(self) -> Result<SinceMetric, String> {
+        let value = self.0 & VALUE_MASK;
+        match (self.0 & METRIC_MASK) >> METRIC_SHIFT {
+            0 => Ok(SinceMetric::BlockNumber(value)),
+            1 => Ok(SinceMetric::Epoch {
+                number: value & EPOCH_NUMBER_MAX,
+                index: value >> 24 & EPOCH_FRACTION_MAX,
+                length: value >> 40 & EPOCH_FRACTION_MAX,
+            }),
+            2 => Ok(SinceMetric::Timestamp(value)),
+            _ => Err(format!("since {:#x} has the reserved metric 0b11", self.0)),
+        }
+    }
+
+    ///
+    /// Whether the chain accepts the since: no reserved bits, a known metric, and an epoch fraction below 1.
+    /// A relative epoch may leave the fraction 0/0, an absolute one must have a length.
+    pub fn check(self) -> Result<(), String> {
+        if self.0 & RESERVED_MASK != 0 {
+            return Err(format!("since {:#x} sets reserved bits", self.0));
+        }
+        if let SinceMetric::Epoch { number, index, length } = self.metric()? {
+            let well_formed = if self.is_relative() && length == 0 { index == 0 } else { index < length };
+            if !well_formed {
+                return Err(format!("{} epoch {} {}/{} is not a fraction below 1", if self.is_relative() { "relative" } else { "absolute" }, number, index, length));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Since> for u64 {
+    fn from(since: Since) -> u64 {
+        since.0
+    }
+}
+
+impl fmt::Display for Since {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "no since");
+        }
+        let kind = if self.is_relative() { "relative" } else { "absolute" };
+        match self.metric() {
+            Ok(SinceMetric::BlockNumber(number)) => write!(f, "{} block number {}", kind, number),
+            Ok(SinceMetric::Epoch { number, index, length }) => write!(f, "{} epoch {} {}/{}", kind, number, index, length),
+            Ok(SinceMetric::Timestamp(seconds)) => write!(f, "{} timestamp {}", kind, seconds),
+            Err(_) => write!(f, "raw since {:#x}", self.0),
+        }
+    }
+}
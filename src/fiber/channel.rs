@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use ckb_testtool::ckb_crypto::secp::{Generator, Privkey, Pubkey};
+use ckb_testtool::ckb_types::core::TransactionView;
+use ckb_testtool::ckb_types::packed::OutPoint;
+use crate::capacity::Capacity;
+use crate::chain::ledger::LedgerError;
+use crate::chain::since::Since;
+use crate::ContractUtil;
+use crate::fiber::commitment::{pubkey_hash, sign, CommitmentScript, HtlcType};
+use crate::cells::commitment_lock::PendingHtlc;
+use crate::fiber::funding::FundingSigner;
+use crate::fiber::settlement::{CommitmentState, Settlement, Unlock};
+
+const MAX_CYCLES: u64 = 100_000_000;
+
+/// the contracts a channel is locked with, deployed once per `ContractUtil`
+pub struct ChannelContracts {
+    pub funding_lock: OutPoint,
+    pub commitment_lock: OutPoint,
+    pub auth: OutPoint,
+}
+
+impl ChannelContracts {
+    pub fn deploy(ct: &mut ContractUtil) -> Self {
+        ChannelContracts {
+            funding_lock: ct.deploy_contract("funding-lock"),
+            commitment_lock: ct.deploy_contract("commitment-lock"),
+            auth: ct.deploy_contract("auth"),
+        }
+    }
+}
+
+///
+/// A ckb channel played against the funding-lock and commitment-lock contracts, from its funding to the settlement of its commitments.
+///
+/// Every transaction goes through the ledger of the `ContractUtil` and the consensus checks of a node, so the funding cell
+/// can only be spent by one commitment and a commitment cell by one settlement, and every output holds its occupied capacity:
+/// an htlc payment, paid out to a wallet cell, must be at least the 62 CKB that cell occupies. The channel holds every key it made, a settlement is signed by the key it asks for.
+///
+/// ```ignore
+/// let mut channel = Channel::open(&mut ct, 2, Capacity::ckb(1000), Since::relative_epoch(10, 1, 2)).unwrap();
+/// let htlc = channel.htlc(HtlcType::OFFERED, 100 * SHANNONS_PER_CKB as u128, &preimage, Since::absolute_timestamp(1711976400));
+/// let commitment = channel.commit(&mut ct, vec![htlc]).unwrap();
+/// let (settlement, tx) = channel.settlement_tx(&mut ct, &commitment, &Unlock::HtlcPreimage { index: 0, preimage });
+/// ct.commit_strict_tx(&tx, MAX_CYCLES).unwrap();
+/// ```
+pub struct Channel {
+    pub contracts: ChannelContracts,
+    pub signer: FundingSigner,
+    /// the live funding cell
+    pub funding: OutPoint,
+    /// capacity of the funding cell, all of it goes to the commitment cells
+    pub capacity: Capacity,
+    pub local_delay: Since,
+    local_delay_key: Pubkey,
+    revocation_key: Pubkey,
+    generator: Generator,
+    // every key of the channel by its pubkey hash
+    keys: HashMap<[u8; 20], Privkey>,
+}
+
+/// a commitment transaction and the cell it creates
+#[derive(Clone, Debug)]
+pub struct Commitment {
+    pub state: CommitmentState,
+    pub tx: TransactionView,
+    pub out_point: OutPoint,
+}
+
+impl Channel {
+    ///
+    /// Deploy the contracts and commit a funding tx locking `capacity` of a wallet cell
+    /// with the MuSig2 key of `parties` random parties.
+    pub fn open(ct: &mut ContractUtil, parties: usize, capacity: Capacity, local_delay: Since) -> Result<Channel, LedgerError> {
+        let contracts = ChannelContracts::deploy(ct);
+        let signer = FundingSigner::random(parties);
+        let always_success = ct.alway_contract.clone();
+        let funding_tx = ct.tx()
+            .input(&always_success, None, &signer.cell(), capacity)
+            .output(&contracts.funding_lock, None, &signer.cell(), capacity)
+            .build();
+        let funding = ct.commit_strict_tx(&funding_tx, MAX_CYCLES)?.remove(0);
+
+        let mut generator = Generator::new();
+        let mut keys = HashMap::new();
+        let local_delay_key = new_key(&mut generator, &mut keys);
+        let revocation_key = new_key(&mut generator, &mut keys);
+        Ok(Channel {
+            contracts,
+            signer,
+            funding,
+            capacity,
+            local_delay,
+            local_delay_key,
+            revocation_key,
+            generator,
+            keys,
+        })
+    }
+
+    /// an htlc paid by `preimage` with remote and local keys of its own
+    pub fn htlc(&mut self, htlc_type: HtlcType, payment_amount: u128, preimage: &[u8; 32], expiry: Since) -> PendingHtlc {
+        let remote_htlc_key = new_key(&mut self.generator, &mut self.keys);
+        let local_htlc_key = new_key(&mut self.generator, &mut self.keys);
+        PendingHtlc::with_preimage(htlc_type, payment_amount, preimage, &remote_htlc_key, &local_htlc_key, expiry.as_u64())
+    }
+
+    /// the script of a commitment with `htlcs`
+    pub fn commitment_script(&self, htlcs: Vec<PendingHtlc>) -> CommitmentScript {
+        let mut script = CommitmentScript::new(self.local_delay.as_u64(), &self.local_delay_key, &self.revocation_key);
+        script.pending_htlcs = htlcs;
+        script
+    }
+
+    ///
+    /// A commitment tx spending the funding cell into a commitment cell with `htlcs`, signed by all the parties.
+    /// It is not committed: a channel makes many commitments, one of them goes on chain.
+    pub fn commitment_tx(&self, ct: &mut ContractUtil, htlcs: Vec<PendingHtlc>) -> Commitment {
+        let state = CommitmentState::ckb(self.commitment_script(htlcs), self.capacity);
+        let tx = ct.tx()
+            .spend(&self.funding)
+            .output(&self.contracts.commitment_lock, None, &state.cell(), state.capacity)
+            .dep(&self.contracts.auth)
+            .build();
+        let mut funding_cell = self.signer.cell();
+        funding_cell.witness = Some(self.signer.witness(&tx));
+        let tx = ct.set_witness(tx, 0, &funding_cell);
+        let out_point = OutPoint::new(tx.hash(), 0);
+        Commitment { state, tx, out_point }
+    }
+
+    /// make a commitment tx and commit it
+    pub fn commit(&self, ct: &mut ContractUtil, htlcs: Vec<PendingHtlc>) -> Result<Commitment, LedgerError> {
+        let commitment = self.commitment_tx(ct, htlcs);
+        ct.commit_strict_tx(&commitment.tx, MAX_CYCLES)?;
+        Ok(commitment)
+    }
+
+    ///
+    /// A tx spending the commitment cell as `unlock` says, signed by the key the contract expects.
+    /// Output 0 is the next commitment cell when an htlc is settled, with the payment in output 1;
+    /// the whole cell goes to output 0 otherwise.
+    pub fn settlement_tx(&self, ct: &mut ContractUtil, commitment: &Commitment, unlock: &Unlock) -> (Settlement, TransactionView) {
+        let settlement = commitment.state.settle(unlock);
+        let always_success = ct.alway_contract.clone();
+        let wallet_cell = self.signer.cell();
+        let scenario = ct.tx().spend_with_since(&commitment.out_point, settlement.since);
+        let scenario = match &settlement.output {
+            Some(output) => scenario
+                .output(&self.contracts.commitment_lock, None, &output.cell(), output.capacity)
+                .output(&always_success, None, &wallet_cell, commitment.state.capacity - output.capacity),
+            None => scenario.output(&always_success, None, &wallet_cell, commitment.state.capacity),
+        };
+        let tx = scenario.dep(&self.contracts.auth).build();
+
+        let key = self.keys.get(&settlement.signer).expect("the channel made every key of its scripts");
+        let mut commitment_cell = commitment.state.cell();
+        commitment_cell.witness = Some(settlement.witness(sign(key, &tx)));
+        let tx = ct.set_witness(tx, 0, &commitment_cell);
+        (settlement, tx)
+    }
+
+    ///
+    /// Settle the commitment cell and commit the tx, returning the commitment left when an htlc was settled.
+    pub fn settle(&self, ct: &mut ContractUtil, commitment: &Commitment, unlock: &Unlock) -> Result<Option<Commitment>, LedgerError> {
+        let (settlement, tx) = self.settlement_tx(ct, commitment, unlock);
+        let out_points = ct.commit_strict_tx(&tx, MAX_CYCLES)?;
+        Ok(settlement.output.map(|state| Commitment {
+            state,
+            tx,
+            out_point: out_points[0].clone(),
+        }))
+    }
+}
+
+// a new key pair, the private key kept by its pubkey hash
+fn new_key(generator: &mut Generator, keys: &mut HashMap<[u8; 20], Privkey>) -> Pubkey {
+    let (privkey, pubkey) = generator.gen_keypair();
+    keys.insert(pubkey_hash(&pubkey), privkey);
+    pubkey
+}
@@ -0,0 +1,246 @@
+use ckb_testtool::ckb_crypto::secp::{Privkey, Pubkey};
+use ckb_testtool::ckb_types::core::TransactionView;
+use ckb_testtool::ckb_types::prelude::Entity;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use crate::cell_message::encoding::Encoding;
+use crate::cell_message::witness_args::EmptyWitnessArgs;
+use crate::cells::commitment_lock::{CommitmentWitness, PendingHtlc};
+use crate::fiber::blake160;
+
+///
+/// The parameters a commitment-lock cell is locked with, its args being
+/// blake160(local_delay_epoch || local_delay_pubkey_hash || revocation_pubkey_hash || pending htlcs).
+///
+/// ```ignore
+/// let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_key.1, &revocation_key.1)
+///     .with_htlc(PendingHtlc::with_preimage(HtlcType::OFFERED, amount, &preimage, &remote_htlc_key.1, &local_htlc_key.1, expiry.as_u64()));
+/// let cell = CommitmentCell::new(script.lock_arg(), None, 0, None);
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct CommitmentScript {
+    /// since value of the local delay
+    pub local_delay_epoch: u64,
+    pub local_delay_pubkey_hash: [u8; 20],
+    pub revocation_pubkey_hash: [u8; 20],
+    pub pending_htlcs: Vec<PendingHtlc>,
+}
+
+impl CommitmentScript {
+    pub fn new(local_delay_epoch: u64, local_delay_pubkey: &Pubkey, revocation_pubkey: &Pubkey) -> Self {
+        CommitmentScript {
+            local_delay_epoch,
+            local_delay_pubkey_hash: pubkey_hash(local_delay_pubkey),
+            revocation_pubkey_hash: pubkey_hash(revocation_pubkey),
+            pending_htlcs: Vec::new(),
+        }
+    }
+
+    pub fn with_htlc(mut self, htlc: PendingHtlc) -> Self {
+        self.pending_htlcs.push(htlc);
+        self
+    }
+
+    /// the bytes hashed into the args, also the start of the witness after the empty `WitnessArgs`
+    pub fn witness_script(&self) -> Vec<u8> {
+        let mut script = [
+            self.local_delay_epoch.to_le_bytes().to_vec(),
+            self.local_delay_pubkey_hash.to_vec(),
+            self.revocation_pubkey_hash.to_vec(),
+        ].concat();
+        for htlc in &self.pending_htlcs {
+            script.extend(Encoding::Raw.encode(htlc).expect("encode pending htlc"));
+        }
+        script
+    }
+
+    pub fn lock_arg(&self) -> [u8; 20] {
+        blake160(self.witness_script())
+    }
+
+    /// the witness unlocking a cell locked by this script
+    pub fn witness(&self, unlock_type: UnlockType, signature: [u8; 65], preimage: Option<[u8; 32]>) -> CommitmentWitness {
+        CommitmentWitness {
+            witness_args: EmptyWitnessArgs::default(),
+            local_delay_epoch: self.local_delay_epoch,
+            local_delay_pubkey_hash: self.local_delay_pubkey_hash,
+            revocation_pubkey_hash: self.revocation_pubkey_hash,
+            pending_htlc: self.pending_htlcs.clone(),
+            unlock_type,
+            signature,
+            preimage,
+        }
+    }
+}
+
+///
+/// The `unlock_type` byte of a commitment witness. Revocation and local delay unlocks share 0xFF,
+/// the since of the input tells them apart; any other value is the index of the htlc to settle,
+/// so `Htlc(0xFF)` has no byte and fails to encode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnlockType {
+    Revocation,
+    LocalDelay,
+    Htlc(u8),
+    /// the byte as is, for values the contract must reject
+    Raw(u8),
+}
+
+impl UnlockType {
+    /// unlock type of the revocation and local delay unlocks
+    pub const WITHOUT_HTLC: u8 = 0xFF;
+}
+
+impl TryFrom<UnlockType> for u8 {
+    type Error = String;
+
+    fn try_from(unlock_type: UnlockType) -> Result<u8, String> {
+        match unlock_type {
+            UnlockType::Revocation | UnlockType::LocalDelay => Ok(UnlockType::WITHOUT_HTLC),
+            UnlockType::Htlc(UnlockType::WITHOUT_HTLC) => Err("0xFF is not an htlc index, use UnlockType::Raw".to_string()),
+            UnlockType::Htlc(index) => Ok(index),
+            UnlockType::Raw(unlock_type) => Ok(unlock_type),
+        }
+    }
+}
+
+/// 0xFF is `Revocation`, a local delay unlock has the same byte
+impl From<u8> for UnlockType {
+    fn from(unlock_type: u8) -> UnlockType {
+        match unlock_type {
+            UnlockType::WITHOUT_HTLC => UnlockType::Revocation,
+            index => UnlockType::Htlc(index),
+        }
+    }
+}
+
+/// how the payment hash of an htlc is computed from its preimage, truncated to 20 bytes
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HashAlgorithm {
+    /// blake2b-256 with the ckb personalization
+    #[default]
+    CkbHash,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub fn payment_hash(self, preimage: impl AsRef<[u8]>) -> [u8; 20] {
+        match self {
+            HashAlgorithm::CkbHash => blake160(preimage),
+            HashAlgorithm::Sha256 => Sha256::digest(preimage)[0..20].try_into().unwrap(),
+        }
+    }
+}
+
+/// whether the local party offered the htlc or received it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HtlcDirection {
+    #[default]
+    Offered,
+    Received,
+}
+
+///
+/// The `htlc_type` byte of a pending htlc: bit 0 is set for a received htlc,
+/// bit 1 for a sha256 payment hash, the other bits are kept as is in `reserved`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct HtlcType {
+    pub direction: HtlcDirection,
+    pub hash_algorithm: HashAlgorithm,
+    /// bits 2..8 of the byte, 0 for any htlc fiber makes
+    pub reserved: u8,
+}
+
+impl HtlcType {
+    pub const OFFERED: HtlcType = HtlcType::new(HtlcDirection::Offered, HashAlgorithm::CkbHash);
+    pub const RECEIVED: HtlcType = HtlcType::new(HtlcDirection::Received, HashAlgorithm::CkbHash);
+
+    pub const fn new(direction: HtlcDirection, hash_algorithm: HashAlgorithm) -> Self {
+        HtlcType { direction, hash_algorithm, reserved: 0 }
+    }
+
+    pub fn with_hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    pub fn payment_hash(self, preimage: impl AsRef<[u8]>) -> [u8; 20] {
+        self.hash_algorithm.payment_hash(preimage)
+    }
+}
+
+impl From<HtlcType> for u8 {
+    fn from(htlc_type: HtlcType) -> u8 {
+        let direction = match htlc_type.direction {
+            HtlcDirection::Offered => 0,
+            HtlcDirection::Received => 0b01,
+        };
+        let hash_algorithm = match htlc_type.hash_algorithm {
+            HashAlgorithm::CkbHash => 0,
+            HashAlgorithm::Sha256 => 0b10,
+        };
+        direction | hash_algorithm | htlc_type.reserved
+    }
+}
+
+impl From<u8> for HtlcType {
+    fn from(htlc_type: u8) -> HtlcType {
+        HtlcType {
+            direction: if htlc_type & 0b01 == 0 { HtlcDirection::Offered } else { HtlcDirection::Received },
+            hash_algorithm: if htlc_type & 0b10 == 0 { HashAlgorithm::CkbHash } else { HashAlgorithm::Sha256 },
+            reserved: htlc_type & !0b11,
+        }
+    }
+}
+
+// the byte of a pending htlc
+impl Serialize for HtlcType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(u8::from(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for HtlcType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(HtlcType::from)
+    }
+}
+
+impl PendingHtlc {
+    pub fn new(htlc_type: HtlcType, payment_amount: u128, payment_hash: [u8; 20], remote_htlc_pubkey: &Pubkey, local_htlc_pubkey: &Pubkey, htlc_expiry: u64) -> Self {
+        PendingHtlc {
+            htlc_type,
+            payment_amount,
+            payment_hash,
+            remote_htlc_pubkey_hash: pubkey_hash(remote_htlc_pubkey),
+            local_htlc_pubkey_hash: pubkey_hash(local_htlc_pubkey),
+            htlc_expiry,
+        }
+    }
+
+    /// the `htlc_type` byte as is, reserved bits included
+    pub fn with_raw_htlc_type(mut self, htlc_type: u8) -> Self {
+        self.htlc_type = HtlcType::from(htlc_type);
+        self
+    }
+
+    /// an htlc paid by `preimage`, hashed with the algorithm of `htlc_type`
+    pub fn with_preimage(htlc_type: HtlcType, payment_amount: u128, preimage: &[u8; 32], remote_htlc_pubkey: &Pubkey, local_htlc_pubkey: &Pubkey, htlc_expiry: u64) -> Self {
+        Self::new(htlc_type, payment_amount, htlc_type.payment_hash(preimage), remote_htlc_pubkey, local_htlc_pubkey, htlc_expiry)
+    }
+}
+
+/// blake160 of the compressed public key, as the commitment-lock args and witness hold it
+pub fn pubkey_hash(pubkey: &Pubkey) -> [u8; 20] {
+    blake160(pubkey.serialize())
+}
+
+/// the recoverable signature of the tx hash, which is the message commitment-lock verifies
+pub fn sign(key: &Privkey, tx: &TransactionView) -> [u8; 65] {
+    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
+    key.sign_recoverable(&message.into())
+        .expect("sign tx hash")
+        .serialize()
+        .try_into()
+        .unwrap()
+}
@@ -0,0 +1,178 @@
+use ckb_testtool::ckb_hash::blake2b_256;
+use ckb_testtool::ckb_types::core::TransactionView;
+use ckb_testtool::ckb_types::prelude::Entity;
+use musig2::{CompactSignature, FirstRound, KeyAggContext, PartialSignature, PubNonce, SecNonceSpices, SecondRound};
+use secp256k1::rand::{self, RngCore};
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use crate::cells::funding_lock::{FundingCell, FundingWitness};
+use crate::cell_message::witness_args::EmptyWitnessArgs;
+use crate::fiber::blake160;
+
+/// the aggregated key and signature that go into a `FundingWitness`
+#[derive(Clone, PartialEq, Debug)]
+pub struct FundingSignature {
+    /// x-only aggregated public key
+    pub pubkey: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+///
+/// Signs a funding-lock spend with MuSig2, every secret key being one party of the channel.
+///
+/// ```ignore
+/// let signer = FundingSigner::new(vec![sec_key_1, sec_key_2]);
+/// let fc = FundingCell::new(signer.lock_arg(), None, 0, None);
+/// // ... build `tx` spending `fc` as its first input
+/// let fc = FundingCell::new(signer.lock_arg(), None, 0, Some(signer.witness(&tx)));
+/// let tx = ct.set_witness(tx, 0, &fc);
+/// ```
+pub struct FundingSigner {
+    secret_keys: Vec<SecretKey>,
+    key_agg_ctx: KeyAggContext,
+    version: u64,
+}
+
+impl FundingSigner {
+    /// the keys are aggregated in the given order
+    pub fn new(secret_keys: Vec<SecretKey>) -> Self {
+        assert!(!secret_keys.is_empty(), "a funding lock needs at least one key");
+        let secp256k1 = Secp256k1::new();
+        let pub_keys: Vec<PublicKey> = secret_keys.iter().map(|key| key.public_key(&secp256k1)).collect();
+        let key_agg_ctx = KeyAggContext::new(pub_keys).expect("aggregate public keys");
+        FundingSigner {
+            secret_keys,
+            key_agg_ctx,
+            version: 0,
+        }
+    }
+
+    /// `parties` random keys
+    pub fn random(parties: usize) -> Self {
+        Self::new((0..parties).map(|_| SecretKey::new(&mut rand::thread_rng())).collect())
+    }
+
+    /// the keys are aggregated in the order of their compressed public keys, whatever order they are given in
+    pub fn sorted(mut secret_keys: Vec<SecretKey>) -> Self {
+        let secp256k1 = Secp256k1::new();
+        secret_keys.sort_by_key(|key| key.public_key(&secp256k1).serialize());
+        Self::new(secret_keys)
+    }
+
+    /// the same parties, aggregated in another order: `order[i]` is the party aggregated at position `i`
+    pub fn with_key_order(&self, order: &[usize]) -> Self {
+        let mut sorted = order.to_vec();
+        sorted.sort();
+        assert_eq!(sorted, (0..self.parties()).collect::<Vec<_>>(), "{:?} is not an order of {} parties", order, self.parties());
+        self.subset(order)
+    }
+
+    /// a signer made of some of the parties only, aggregating their keys alone
+    pub fn subset(&self, parties: &[usize]) -> Self {
+        Self::new(parties.iter().map(|party| self.secret_keys[*party]).collect()).with_version(self.version)
+    }
+
+    pub fn parties(&self) -> usize {
+        self.secret_keys.len()
+    }
+
+    pub fn secret_keys(&self) -> &[SecretKey] {
+        &self.secret_keys
+    }
+
+    /// the version signed and placed in the witness, 0 by default
+    pub fn with_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn pubkey(&self) -> [u8; 32] {
+        let aggregated_pub_key: PublicKey = self.key_agg_ctx.aggregated_pubkey();
+        aggregated_pub_key.x_only_public_key().0.serialize()
+    }
+
+    /// the funding-lock args, blake2b-160 of the aggregated x-only public key
+    pub fn lock_arg(&self) -> [u8; 20] {
+        blake160(self.pubkey())
+    }
+
+    /// an unsigned funding cell locked by the aggregated key
+    pub fn cell(&self) -> FundingCell {
+        FundingCell::new(self.lock_arg(), None, 0, None)
+    }
+
+    ///
+    /// the message the contract verifies: blake2b_256(version || funding out point || tx hash),
+    /// the funding out point being the one of the first input
+    pub fn message(&self, tx: &TransactionView) -> [u8; 32] {
+        let tx_hash: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
+        blake2b_256([
+            self.version.to_le_bytes().to_vec(),
+            funding_out_point(tx).to_vec(),
+            tx_hash.to_vec(),
+        ].concat())
+    }
+
+    pub fn sign(&self, tx: &TransactionView) -> FundingSignature {
+        FundingSignature {
+            pubkey: self.pubkey(),
+            signature: self.sign_message(self.message(tx)),
+        }
+    }
+
+    /// a witness for the first input of `tx`, which must be complete but for the witnesses
+    pub fn witness(&self, tx: &TransactionView) -> FundingWitness {
+        let FundingSignature { pubkey, signature } = self.sign(tx);
+        FundingWitness {
+            witness_args: EmptyWitnessArgs::default(),
+            version: self.version,
+            funding_out_point: funding_out_point(tx),
+            pubkey,
+            signature,
+        }
+    }
+
+    /// runs the two MuSig2 rounds between all the parties and aggregates their partial signatures
+    pub fn sign_message(&self, message: [u8; 32]) -> [u8; 64] {
+        let mut first_rounds: Vec<FirstRound> = self.secret_keys.iter().enumerate()
+            .map(|(index, sec_key)| {
+                let mut nonce_seed = [0u8; 32];
+                rand::rngs::OsRng.fill_bytes(&mut nonce_seed);
+                FirstRound::new(
+                    self.key_agg_ctx.clone(),
+                    nonce_seed,
+                    index,
+                    SecNonceSpices::new()
+                        .with_seckey(*sec_key)
+                        .with_message(&message),
+                ).expect("start the first round")
+            })
+            .collect();
+
+        let nonces: Vec<PubNonce> = first_rounds.iter().map(|round| round.our_public_nonce()).collect();
+        for (index, round) in first_rounds.iter_mut().enumerate() {
+            for (other, nonce) in nonces.iter().enumerate().filter(|(other, _)| *other != index) {
+                round.receive_nonce(other, nonce.clone()).expect("receive nonce");
+            }
+        }
+
+        let second_rounds: Vec<SecondRound<[u8; 32]>> = first_rounds.into_iter()
+            .zip(&self.secret_keys)
+            .map(|(round, sec_key)| round.finalize(*sec_key, message).expect("finalize the first round"))
+            .collect();
+        let partial_signatures: Vec<PartialSignature> = second_rounds.iter().map(|round| round.our_signature()).collect();
+
+        // any party can aggregate, the first one does
+        let mut aggregator = second_rounds.into_iter().next().unwrap();
+        for (other, partial_signature) in partial_signatures.into_iter().enumerate().skip(1) {
+            aggregator.receive_signature(other, partial_signature).expect("receive partial signature");
+        }
+        let signature: CompactSignature = aggregator.finalize().expect("aggregate signatures");
+        signature.into()
+    }
+}
+
+/// the out point of the first input of `tx`
+pub fn funding_out_point(tx: &TransactionView) -> [u8; 36] {
+    let out_point = tx.inputs().get(0).expect("a funding tx has an input").previous_output();
+    out_point.as_slice().try_into().unwrap()
+}
@@ -0,0 +1,13 @@
+//! Helpers that play the fiber channel parties against the funding-lock and commitment-lock contracts.
+
+pub mod funding;
+pub mod commitment;
+pub mod settlement;
+pub mod channel;
+
+use ckb_testtool::ckb_hash::blake2b_256;
+
+/// the first 20 bytes of blake2b-256, how the fiber scripts hash keys and scripts into their args
+pub fn blake160(data: impl AsRef<[u8]>) -> [u8; 20] {
+    blake2b_256(data)[0..20].try_into().unwrap()
+}
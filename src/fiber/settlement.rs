@@ -0,0 +1,150 @@
+use crate::capacity::Capacity;
+use crate::chain::since::Since;
+use crate::cells::commitment_lock::{CommitmentUdtCell, CommitmentWitness, PendingHtlc};
+use crate::fiber::commitment::{CommitmentScript, HtlcDirection, UnlockType};
+
+///
+/// How a commitment cell is unlocked.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Unlock {
+    /// the revocation key takes the whole cell, at any time
+    Revoke,
+    /// the local delay key takes the whole cell once the local delay has passed
+    LocalDelay,
+    /// htlc `index` is settled with its preimage, by the remote key of an offered htlc or the local key of a received one
+    HtlcPreimage { index: usize, preimage: [u8; 32] },
+    /// htlc `index` is settled after its expiry, by the local key of an offered htlc or the remote key of a received one
+    HtlcTimeout { index: usize },
+}
+
+/// the udt a commitment cell holds, the htlc payments are taken from its amount instead of the capacity
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CommitmentUdt {
+    pub type_arg: [u8; 32],
+    pub amount: u128,
+}
+
+///
+/// A commitment cell as the contract sees it: the script it is locked with and what it holds.
+///
+/// ```ignore
+/// let state = CommitmentState::udt(script, Capacity::shannons(1000), [42; 32], total_sudt_amount);
+/// let settlement = state.settle(&Unlock::HtlcPreimage { index: 0, preimage: preimage1 });
+/// let next = settlement.output.unwrap();
+/// let tx = ct.tx()
+///     .input(&commitment_lock_contract, Some(&udt_contract), &state.cell(), state.capacity)
+///     .output(&commitment_lock_contract, Some(&udt_contract), &next.cell(), next.capacity)
+///     .dep(&auth_contract)
+///     .build();
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct CommitmentState {
+    pub script: CommitmentScript,
+    pub capacity: Capacity,
+    pub udt: Option<CommitmentUdt>,
+}
+
+impl CommitmentState {
+    pub fn ckb(script: CommitmentScript, capacity: Capacity) -> Self {
+        CommitmentState { script, capacity, udt: None }
+    }
+
+    pub fn udt(script: CommitmentScript, capacity: Capacity, type_arg: [u8; 32], amount: u128) -> Self {
+        CommitmentState { script, capacity, udt: Some(CommitmentUdt { type_arg, amount }) }
+    }
+
+    /// the cell locked by the script, a ckb cell has no type args and a zero amount
+    pub fn cell(&self) -> CommitmentUdtCell {
+        CommitmentUdtCell::new(
+            self.script.lock_arg(),
+            self.udt.map(|udt| udt.type_arg),
+            self.udt.map_or(0, |udt| udt.amount),
+            None,
+        )
+    }
+
+    ///
+    /// What the contract expects of a transaction unlocking this cell with `unlock`.
+    ///
+    /// Settling an htlc removes it from the script and pays its amount out of the cell:
+    /// the udt amount is reduced for a udt cell, whose capacity stays, the capacity otherwise.
+    /// Panics if there is no htlc `index` or its payment is more than the cell holds,
+    /// the contract fails with `InvalidUnlockType` and `Panic` on these,
+    /// and if `index` is past 254, which the unlock type byte cannot address.
+    pub fn settle(&self, unlock: &Unlock) -> Settlement {
+        let script = &self.script;
+        let (unlock_type, preimage, signer, since, output) = match *unlock {
+            Unlock::Revoke => (UnlockType::Revocation, None, script.revocation_pubkey_hash, Since::NONE, None),
+            Unlock::LocalDelay => (UnlockType::LocalDelay, None, script.local_delay_pubkey_hash, Since::raw(script.local_delay_epoch), None),
+            Unlock::HtlcPreimage { index, preimage } => {
+                let htlc = self.htlc(index);
+                let signer = if htlc.htlc_type.direction == HtlcDirection::Offered { htlc.remote_htlc_pubkey_hash } else { htlc.local_htlc_pubkey_hash };
+                (htlc_unlock_type(index), Some(preimage), signer, Since::NONE, Some(self.without_htlc(index)))
+            }
+            Unlock::HtlcTimeout { index } => {
+                let htlc = self.htlc(index);
+                let signer = if htlc.htlc_type.direction == HtlcDirection::Offered { htlc.local_htlc_pubkey_hash } else { htlc.remote_htlc_pubkey_hash };
+                (htlc_unlock_type(index), None, signer, Since::raw(htlc.htlc_expiry), Some(self.without_htlc(index)))
+            }
+        };
+        Settlement {
+            input: script.clone(),
+            unlock_type,
+            preimage,
+            signer,
+            since,
+            output,
+        }
+    }
+
+    fn htlc(&self, index: usize) -> &PendingHtlc {
+        self.script.pending_htlcs.get(index)
+            .unwrap_or_else(|| panic!("no htlc {} in a script of {} htlcs", index, self.script.pending_htlcs.len()))
+    }
+
+    // the cell left after paying out htlc `index`
+    fn without_htlc(&self, index: usize) -> CommitmentState {
+        let mut next = self.clone();
+        let htlc = next.script.pending_htlcs.remove(index);
+        match next.udt.as_mut() {
+            Some(udt) => {
+                udt.amount = udt.amount.checked_sub(htlc.payment_amount).expect("htlc payment exceeds the udt amount");
+            }
+            None => {
+                next.capacity = u64::try_from(htlc.payment_amount).ok()
+                    .and_then(|payment| next.capacity.checked_sub(Capacity::shannons(payment)))
+                    .expect("htlc payment exceeds the capacity");
+            }
+        }
+        next
+    }
+}
+
+// 0xFF is the revocation and local delay unlocks, so htlcs past 254 cannot be unlocked
+fn htlc_unlock_type(index: usize) -> UnlockType {
+    match u8::try_from(index) {
+        Ok(index) if index != UnlockType::WITHOUT_HTLC => UnlockType::Htlc(index),
+        _ => panic!("htlc {} has no unlock type, the unlock type byte addresses htlcs 0..255", index),
+    }
+}
+
+///
+/// The expected shape of a transaction unlocking a commitment cell, see `CommitmentState::settle`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settlement {
+    /// the script of the unlocked cell, repeated in the witness
+    pub input: CommitmentScript,
+    pub unlock_type: UnlockType,
+    pub preimage: Option<[u8; 32]>,
+    /// pubkey hash of the key that must sign the transaction
+    pub signer: [u8; 20],
+    pub since: Since,
+    /// the commitment cell output 0 must be, `None` when the signer takes the whole cell
+    pub output: Option<CommitmentState>,
+}
+
+impl Settlement {
+    pub fn witness(&self, signature: [u8; 65]) -> CommitmentWitness {
+        self.input.witness(self.unlock_type, signature, self.preimage)
+    }
+}
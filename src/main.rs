@@ -1,7 +1,7 @@
 extern crate core;
 
 use ckb_testtool::{
-    ckb_error::Error,
+    ckb_error::{Error, InternalErrorKind},
     ckb_types::{
         bytes::Bytes,
         core::{Cycle, TransactionView},
@@ -14,26 +14,43 @@ use std::path::PathBuf;
 use std::str::FromStr;
 use ckb_std::ckb_types::packed::Script;
 
-use ckb_testtool::ckb_types::packed::{CellDep, CellInput, CellOutput, CellOutputBuilder, OutPoint, ScriptOptBuilder};
+use ckb_testtool::ckb_types::core::Capacity as CoreCapacity;
+use ckb_testtool::ckb_types::core::HeaderView;
+use ckb_testtool::ckb_types::packed::{Byte32, CellDep, CellInput, CellOutput, CellOutputBuilder, OutPoint, ScriptOptBuilder};
 use ckb_testtool::ckb_types::prelude::{Builder, Entity, Pack, Unpack};
 use serde_molecule::from_slice;
+use crate::capacity::Capacity;
 use crate::cell_message::cell::Cell;
-use crate::prelude::ContextExt;
+use crate::cell_message::error::OutputError;
+use crate::chain::clock::ChainClock;
+use crate::chain::consensus;
+use crate::chain::ledger::{Ledger, LedgerError};
+use crate::chain::since::Since;
+use crate::prelude::{ContextExt, ContractError, ScriptFailure, ScriptKind, StrictContext};
+use crate::scenario::TxScenario;
 
 #[cfg(test)]
 pub(crate) mod utilities;
 mod tests;
 mod cells;
 mod cell_message;
+mod scenario;
+mod script_error;
+mod fiber;
+mod chain;
+mod capacity;
 
 pub mod prelude {
     use ckb_testtool::{
         ckb_error::Error,
         ckb_types::core::{Cycle, TransactionView},
+        context::Context,
     };
+    pub use crate::script_error::{ContractError, ScriptFailure, ScriptKind};
 
     pub const MAX_CYCLES: u64 = 10_000_000;
     pub const SPV_CELL_CAP: u64 = 500;
+    pub const SHANNONS_PER_CKB: u64 = 100_000_000;
     pub const SPV_HEADERS_GROUP_SIZE: usize = 20; // Speed up to save time.
 
     // This helper method runs Context::verify_tx, but in case error happens,
@@ -41,7 +58,21 @@ pub mod prelude {
     pub trait ContextExt {
         fn should_be_passed(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error>;
         fn should_be_failed(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error>;
+        // Asserts that the script group starting at `index` fails with exit `code`,
+        // panics with both the expected and the actual failure otherwise.
+        fn should_fail_with<E: ContractError>(&self, tx: &TransactionView, max_cycles: u64, kind: ScriptKind, index: usize, error: E) -> ScriptFailure;
+        // The same assertions with the checks a node runs before the scripts, see `chain::consensus::check_tx`.
+        fn strict(&self) -> StrictContext<'_>;
     }
+
+    ///
+    /// A `Context` whose transactions must also pass the consensus checks of a node, opted in with `context.strict()`:
+    ///
+    /// ```ignore
+    /// ct.context.strict().should_be_passed(&tx, MAX_CYCLES).unwrap();
+    /// ```
+    #[derive(Clone, Copy)]
+    pub struct StrictContext<'a>(pub &'a Context);
 }
 
 // The exact same Loader code from capsule's template, except that
@@ -122,44 +153,109 @@ impl Loader {
 
 impl prelude::ContextExt for Context {
     fn should_be_passed(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error> {
-        let result = self.verify_tx(tx, max_cycles);
-        if let Err(err) = result {
-            let mut path = env::current_dir().expect("current dir");
-            path.push("failed_txs");
-            std::fs::create_dir_all(&path).expect("create failed_txs dir");
-            let mock_tx = self.dump_tx(tx).expect("dump failed tx");
-            let json = serde_json::to_string_pretty(&mock_tx).expect("json");
-            path.push(format!("0x{:x}.json", tx.hash()));
-            println!("Failed tx written to {:?}", path);
-            std::fs::write(path, json).expect("write");
-            panic!("should be passed, but failed since {err}");
-        }
-        result
+        assert_passed(self, tx, self.verify_tx(tx, max_cycles))
     }
 
     fn should_be_failed(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error> {
-        let result = self.verify_tx(tx, max_cycles);
-        if result.is_ok() {
-            let mut path = env::current_dir().expect("current dir");
-            path.push("failed_txs");
-            std::fs::create_dir_all(&path).expect("create failed_txs dir");
-            let mock_tx = self.dump_tx(tx).expect("dump failed tx");
-            let json = serde_json::to_string_pretty(&mock_tx).expect("json");
-            path.push(format!("0x{:x}.json", tx.hash()));
-            println!("Failed tx written to {:?}", path);
-            std::fs::write(path, json).expect("write");
-            panic!("should be failed");
+        assert_failed(self, tx, self.verify_tx(tx, max_cycles))
+    }
+
+    fn should_fail_with<E: ContractError>(&self, tx: &TransactionView, max_cycles: u64, kind: ScriptKind, index: usize, error: E) -> ScriptFailure {
+        assert_failed_with(self, tx, self.verify_tx(tx, max_cycles), kind, index, error)
+    }
+
+    fn strict(&self) -> StrictContext<'_> {
+        StrictContext(self)
+    }
+}
+
+impl prelude::ContextExt for StrictContext<'_> {
+    fn should_be_passed(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error> {
+        assert_passed(self.0, tx, self.verify_tx(tx, max_cycles))
+    }
+
+    fn should_be_failed(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error> {
+        assert_failed(self.0, tx, self.verify_tx(tx, max_cycles))
+    }
+
+    fn should_fail_with<E: ContractError>(&self, tx: &TransactionView, max_cycles: u64, kind: ScriptKind, index: usize, error: E) -> ScriptFailure {
+        assert_failed_with(self.0, tx, self.verify_tx(tx, max_cycles), kind, index, error)
+    }
+
+    fn strict(&self) -> StrictContext<'_> {
+        *self
+    }
+}
+
+impl StrictContext<'_> {
+    ///
+    /// `Context::verify_tx` after the consensus checks, a consensus error is reported as an internal error
+    /// whose message starts with `consensus:`
+    pub fn verify_tx(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, Error> {
+        consensus::check_tx(self.0, tx)
+            .map_err(|err| Error::from(InternalErrorKind::Other.other(format!("consensus: {err}"))))?;
+        self.0.verify_tx(tx, max_cycles)
+    }
+}
+
+fn assert_passed(context: &Context, tx: &TransactionView, result: Result<Cycle, Error>) -> Result<Cycle, Error> {
+    if let Err(err) = &result {
+        dump_failed_tx(context, tx);
+        panic!("should be passed, but failed since {err}");
+    }
+    result
+}
+
+fn assert_failed(context: &Context, tx: &TransactionView, result: Result<Cycle, Error>) -> Result<Cycle, Error> {
+    if result.is_ok() {
+        dump_failed_tx(context, tx);
+        panic!("should be failed");
+    }
+    result
+}
+
+fn assert_failed_with<E: ContractError>(context: &Context, tx: &TransactionView, result: Result<Cycle, Error>, kind: ScriptKind, index: usize, error: E) -> ScriptFailure {
+    let code = error.code();
+    let expected = format!("{} to fail with {} (code {})", kind.source(index), error.name(), code);
+    let err = match result {
+        Ok(_) => {
+            dump_failed_tx(context, tx);
+            panic!("expected {expected}, but the tx passed");
+        }
+        Err(err) => err,
+    };
+    match ScriptFailure::parse(&err) {
+        Some(failure) if failure.matches(kind, index, code) => failure,
+        Some(failure) => {
+            dump_failed_tx(context, tx);
+            panic!("expected {expected}, but {}", failure.describe::<E>());
+        }
+        None => {
+            dump_failed_tx(context, tx);
+            panic!("expected {expected}, but failed with a non script error: {err}");
         }
-        result
     }
 }
 
+// dumps the transaction to the failed_txs folder, so it can be replayed with ckb-debugger
+fn dump_failed_tx(context: &Context, tx: &TransactionView) {
+    let mut path = env::current_dir().expect("current dir");
+    path.push("failed_txs");
+    std::fs::create_dir_all(&path).expect("create failed_txs dir");
+    let mock_tx = context.dump_tx(tx).expect("dump failed tx");
+    let json = serde_json::to_string_pretty(&mock_tx).expect("json");
+    path.push(format!("0x{:x}.json", tx.hash()));
+    println!("Failed tx written to {:?}", path);
+    std::fs::write(path, json).expect("write");
+}
+
 
 pub struct ContractUtil {
     pub loader: Loader,
     pub context: Context,
     pub alway_contract: OutPoint,
-
+    pub clock: ChainClock,
+    pub ledger: Ledger,
 }
 
 impl ContractUtil {
@@ -169,11 +265,15 @@ impl ContractUtil {
 
         let stack_reorder_bin = loader.load_binary("always_success");
         let out_point = context.deploy_cell(stack_reorder_bin);
+        let clock = ChainClock::default();
+        context.insert_header(clock.tip().clone());
 
         return Self {
             loader: loader,
             context: context,
             alway_contract: out_point,
+            clock: clock,
+            ledger: Ledger::default(),
         };
     }
 
@@ -182,114 +282,192 @@ impl ContractUtil {
         self.context.deploy_cell(stack_reorder_bin)
     }
 
+    ///
+    /// start a chainable transaction scenario from an empty tx
+    pub fn tx(&mut self) -> TxScenario<'_> {
+        TxScenario::new(self)
+    }
+
+    ///
+    /// continue a chainable transaction scenario from an existing tx
+    pub fn tx_from(&mut self, tx_builder: TransactionView) -> TxScenario<'_> {
+        TxScenario::from_tx(self, tx_builder)
+    }
+
     ///
     /// create input cell, add input cell to tx
-    pub fn add_input(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, redundant_cap: usize) -> TransactionView {
-        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, redundant_cap);
+    pub fn add_input(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, capacity: Capacity) -> TransactionView {
+        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, capacity);
 
         // data
         let out_point1 = self.context.create_cell(cell_output.build(), cell_tx.get_data().into());
+        self.track_cell(&out_point1);
         let input = CellInput::new_builder()
             .previous_output(out_point1).build();
-        tx_builder.as_advanced_builder()
-            .input(input).build()
+        let tx_builder = tx_builder.as_advanced_builder()
+            .input(input).build();
+        self.attach_input_witness(tx_builder, cell_tx)
     }
 
-    pub fn add_input_with_since(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, since: u64, redundant_cap: usize) -> TransactionView {
-        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, redundant_cap);
+    pub fn add_input_with_since(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, since: Since, capacity: Capacity) -> TransactionView {
+        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, capacity);
 
         // data
         let out_point1 = self.context.create_cell(cell_output.build(), cell_tx.get_data().into());
+        self.track_cell(&out_point1);
         let input = CellInput::new_builder()
-            .since(since.pack())
+            .since(since.as_u64().pack())
             .previous_output(out_point1).build();
+        let tx_builder = tx_builder.as_advanced_builder()
+            .input(input).build();
+        self.attach_input_witness(tx_builder, cell_tx)
+    }
+
+    // the witness of a spent cell belongs to the input that was just added
+    fn attach_input_witness(&self, tx_builder: TransactionView, cell_tx: &dyn Cell) -> TransactionView {
+        match cell_tx.get_witness() {
+            None => tx_builder,
+            Some(witness) => {
+                let input_index = tx_builder.inputs().len() - 1;
+                self.set_witness_bytes(tx_builder, input_index, Bytes::from(witness))
+            }
+        }
+    }
+
+    pub fn create_tx_cells(&mut self, tx_build: TransactionView) {
+        if let Err(err) = self.commit_tx(&tx_build, 10_000_000) {
+            dump_failed_tx(&self.context, &tx_build);
+            panic!("failed to create the cells of the tx: {}", err);
+        }
+    }
+
+    ///
+    /// verify `tx` against the ledger, the since of its inputs at the chain tip and the scripts,
+    /// then spend its inputs and create its outputs as live cells at their out points, returned in output order
+    pub fn commit_tx(&mut self, tx: &TransactionView, max_cycles: u64) -> Result<Vec<OutPoint>, LedgerError> {
+        self.ledger.check_tx(tx)?;
+        self.clock.check_tx(tx).map_err(LedgerError::ImmatureInput)?;
+        self.context.verify_tx(tx, max_cycles).map_err(|err| LedgerError::Verification(err.to_string()))?;
+        let out_points = self.ledger.apply_tx(tx);
+        for (out_point, (cell, data)) in out_points.iter().zip(tx.outputs_with_data_iter()) {
+            self.context.create_cell_with_out_point(out_point.clone(), cell, data);
+            self.clock.commit(&mut self.context, out_point);
+        }
+        Ok(out_points)
+    }
+
+    ///
+    /// `commit_tx` after the checks a node runs before the scripts, see `chain::consensus::check_tx`:
+    /// every output must hold its occupied capacity and the outputs no more than the inputs
+    pub fn commit_strict_tx(&mut self, tx: &TransactionView, max_cycles: u64) -> Result<Vec<OutPoint>, LedgerError> {
+        consensus::check_tx(&self.context, tx).map_err(LedgerError::Consensus)?;
+        self.commit_tx(tx, max_cycles)
+    }
+
+    // a cell created here is live and committed in the tip block
+    fn track_cell(&mut self, out_point: &OutPoint) {
+        self.clock.commit(&mut self.context, out_point);
+        self.ledger.create(out_point);
+    }
+
+    ///
+    /// spend an existing cell, e.g. an output of a committed tx
+    pub fn add_cell_input(&self, tx_builder: TransactionView, out_point: &OutPoint, since: Since) -> TransactionView {
+        let input = CellInput::new_builder()
+            .since(since.as_u64().pack())
+            .previous_output(out_point.clone()).build();
         tx_builder.as_advanced_builder()
             .input(input).build()
     }
 
-    pub fn create_tx_cells(&mut self, tx_build: TransactionView) {
-        self.context.should_be_passed(&tx_build, 10_000_000).unwrap();
-        tx_build.outputs_with_data_iter()
-            .for_each(|(cell, data)|
-            {
-                self.context.create_cell(cell, data);
-            }
-            )
+    /// advance the chain tip by `blocks` blocks, see `ChainClock`
+    pub fn advance_blocks(&mut self, blocks: u64) -> HeaderView {
+        self.clock.advance_blocks(&mut self.context, blocks)
+    }
+
+    /// advance the chain tip by at least `number` epochs and `index / length` of an epoch
+    pub fn advance_epochs(&mut self, number: u64, index: u64, length: u64) -> HeaderView {
+        self.clock.advance_epochs(&mut self.context, number, index, length)
+    }
+
+    /// advance the chain tip by at least `seconds`
+    pub fn advance_seconds(&mut self, seconds: u64) -> HeaderView {
+        self.clock.advance_seconds(&mut self.context, seconds)
+    }
+
+    ///
+    /// check the since of every input is mature at the chain tip, `Context::verify_tx` only runs the scripts
+    /// (`commit_tx` checks it too)
+    pub fn check_since(&self, tx_builder: &TransactionView) -> Result<(), String> {
+        self.clock.check_tx(tx_builder)
+    }
+
+    pub fn add_header_dep(&self, tx_builder: TransactionView, block_hash: &Byte32) -> TransactionView {
+        tx_builder.as_advanced_builder()
+            .header_dep(block_hash.clone())
+            .build()
     }
 
 
-    pub fn create_cell_input_by_cell(&mut self, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, redundant_cap: usize) -> CellInput {
-        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, redundant_cap);
+    pub fn create_cell_input_by_cell(&mut self, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, capacity: Capacity) -> CellInput {
+        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, capacity);
 
         // data
         let out_point1 = self.context.create_cell(cell_output.build(), cell_tx.get_data().into());
+        self.track_cell(&out_point1);
         CellInput::new_builder().previous_output(out_point1).build()
     }
 
-    pub fn add_outpoint(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, redundant_cap: usize) -> TransactionView {
-        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, redundant_cap);
-
-        let witness = match cell_tx.get_witness() {
-            None => {
-                // BytesBuilder::default().build()
-                Bytes::from(vec![])
-            }
-            Some(witness) => {
-                Bytes::from(witness)
-            }
-        };
+    pub fn add_outpoint(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, capacity: Capacity) -> TransactionView {
+        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, capacity);
 
         return tx_builder
             .as_advanced_builder()
             .output(cell_output.build().clone())
             .output_data(Bytes::from(cell_tx.get_data()).pack())
-            .witness(Pack::pack(&witness)).build();
+            .build();
     }
 
-    pub fn get_celloutput_builder(&mut self, lock_contract: &OutPoint, type_contract: &Option<OutPoint>, cell_tx: &dyn Cell, redundant_cap: usize) -> CellOutputBuilder {
+    pub fn get_celloutput_builder(&mut self, lock_contract: &OutPoint, type_contract: &Option<OutPoint>, cell_tx: &dyn Cell, capacity: Capacity) -> CellOutputBuilder {
+        let cell_output = self.build_cell_output(lock_contract, type_contract, cell_tx);
+        let shannons = capacity.resolve(Self::occupied_capacity_of(&cell_output, cell_tx))
+            .unwrap_or_else(|| panic!("capacity overflow: {}", capacity));
+        cell_output.as_builder().capacity(shannons.pack())
+    }
 
+    ///
+    /// minimum capacity a cell with these scripts and data must hold on chain
+    pub fn occupied_capacity(&mut self, lock_contract: &OutPoint, type_contract: &Option<OutPoint>, cell_tx: &dyn Cell) -> Capacity {
+        let cell_output = self.build_cell_output(lock_contract, type_contract, cell_tx);
+        Capacity::shannons(Self::occupied_capacity_of(&cell_output, cell_tx))
+    }
+
+    fn occupied_capacity_of(cell_output: &CellOutput, cell_tx: &dyn Cell) -> u64 {
+        let data_capacity = CoreCapacity::bytes(cell_tx.get_data().len()).unwrap();
+        cell_output.occupied_capacity(data_capacity).unwrap().as_u64()
+    }
 
+    // cell output with lock and type scripts set, capacity is left empty
+    fn build_cell_output(&mut self, lock_contract: &OutPoint, type_contract: &Option<OutPoint>, cell_tx: &dyn Cell) -> CellOutput {
         // lock script
-        let lock_script = self.context.build_script(&lock_contract, cell_tx.get_lock_arg().into()).unwrap();
-
-
-        let mut cell_output = {
-            let mut cell_output = CellOutputBuilder::default()
-                .lock(lock_script);
-            match type_contract {
-                None => {}
-                Some(contract) => {
-                    let script = self.context.build_script(&contract, cell_tx.get_type_arg().unwrap().into()).unwrap();
-                    cell_output = cell_output.type_(ScriptOptBuilder::default()
-                        .set(Some(script)).build());
-                }
+        let lock_script = self.context.build_script(lock_contract, cell_tx.get_lock_arg().into()).unwrap();
+
+        let mut cell_output = CellOutputBuilder::default()
+            .lock(lock_script);
+        match type_contract {
+            None => {}
+            Some(contract) => {
+                let script = self.context.build_script(contract, cell_tx.get_type_arg().unwrap().into()).unwrap();
+                cell_output = cell_output.type_(ScriptOptBuilder::default()
+                    .set(Some(script)).build());
             }
-            cell_output
-        };
-
-        // let data = cell_tx.get_data();
-        cell_output.capacity((redundant_cap as u64).pack())
-        // let expected_length = cell_output.expected_length();
-        // cell_output = cell_output.capacity(
-        //     ((expected_length + data.len() + redundant_cap) as u64).pack()
-        // );
+        }
+        cell_output.build()
     }
 
 
-    pub fn replace_output(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, redundant_cap: usize, replace_index: usize) -> TransactionView {
-        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, redundant_cap);
-
-        let witness = match cell_tx.get_witness() {
-            None => {
-                // BytesBuilder::default().build()
-                Bytes::from(vec![])
-            }
-            Some(witness) => {
-                println!("new witness:{:?}", witness);
-                Bytes::from(witness)
-            }
-        };
-
+    pub fn replace_output(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, capacity: Capacity, replace_index: usize) -> TransactionView {
+        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, capacity);
 
         let data = Bytes::from(cell_tx.get_data());
 
@@ -314,62 +492,16 @@ impl ContractUtil {
             return tx_builder;
         }
 
-        let mut witnessVec = tx_builder.data().witnesses().unpack();
-        if let Some(old_element) = witnessVec.get_mut(replace_index) {
-            *old_element = witness;
-        } else {
-            witnessVec.push(witness);
-            // println!("witness Index {} is out of bounds", replace_index);
-            // return tx_builder;
-        }
-
         tx_builder.as_advanced_builder()
             .set_outputs_data(vec![])
             .outputs_data(output_data.pack())
             .set_outputs(vec![])
             .outputs(output_cells)
-            .set_witnesses(vec![])
-            .witnesses(witnessVec.pack())
             .build()
     }
 
-    pub fn set_output(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, redundant_cap: usize, set_index: usize) -> TransactionView {
-
-
-        // lock script
-        let lock_script = self.context.build_script(&lock_contract, cell_tx.get_lock_arg().into()).unwrap();
-
-        let mut cell_output = {
-            let mut cell_output = CellOutputBuilder::default()
-                .lock(lock_script);
-            match type_contract {
-                None => {}
-                Some(contract) => {
-                    let script = self.context.build_script(&contract, cell_tx.get_type_arg().unwrap().into()).unwrap();
-                    cell_output = cell_output.type_(ScriptOptBuilder::default()
-                        .set(Some(script)).build());
-                }
-            }
-            cell_output
-        };
-
-        // let data = cell_tx.get_data();
-        cell_output = cell_output.capacity((redundant_cap as u64).pack());
-        // let expected_length = cell_output.expected_length();
-        // cell_output = cell_output.capacity(
-        //     ((expected_length + data.len() + redundant_cap) as u64).pack()
-        // );
-
-        let witness = match cell_tx.get_witness() {
-            None => {
-                // BytesBuilder::default().build()
-                Bytes::from(vec![])
-            }
-            Some(witness) => {
-                Bytes::from(witness)
-            }
-        };
-
+    pub fn set_output(&mut self, tx_builder: TransactionView, lock_contract: OutPoint, type_contract: Option<OutPoint>, cell_tx: &dyn Cell, capacity: Capacity, set_index: usize) -> TransactionView {
+        let cell_output = self.get_celloutput_builder(&lock_contract, &type_contract, cell_tx, capacity);
 
         let data = Bytes::from(cell_tx.get_data());
 
@@ -383,24 +515,23 @@ impl ContractUtil {
         let mut output_data = tx_builder.data().raw().outputs_data().unpack();
         output_data.insert(set_index, data);
 
-        let mut witnessVec = tx_builder.data().witnesses().unpack();
-        witnessVec.insert(set_index, witness);
         tx_builder.as_advanced_builder()
             .set_outputs_data(vec![])
             .outputs_data(output_data.pack())
             .set_outputs(vec![])
             .outputs(output_cells)
-            .set_witnesses(vec![])
-            .witnesses(witnessVec.pack())
             .build()
     }
 
-    pub fn get_cell_by_index<T>(&self, tx_builder: TransactionView, index: usize) -> T
+    ///
+    /// decode output `index` of the tx as `T`, failing with the field that does not match `T`
+    pub fn get_cell_by_index<T>(&self, tx_builder: TransactionView, index: usize) -> Result<T, OutputError>
     where
         T: Cell,
     {
         let cells = tx_builder.data().raw().outputs();
-        let cell = cells.get(index).unwrap();
+        let cell = cells.get(index)
+            .ok_or_else(|| OutputError::Missing { index, outputs: cells.len() })?;
         let lock_args = cell.lock().args().unpack();
         let type_args = match cell.type_().to_opt() {
             None => {
@@ -410,19 +541,78 @@ impl ContractUtil {
         };
         // data
         let data = tx_builder.data().raw().outputs_data();
-        let data = data.get(index).unwrap().unpack();
-        // witness input
-        let witness_args_raw_data = match tx_builder.data().witnesses().get(index) {
-            None => {
-                None
-            }
-            Some(witness) => { Some(witness.unpack()) }
-        };
-        T::from_arg(lock_args, type_args, data, witness_args_raw_data)
+        let data = data.get(index)
+            .ok_or_else(|| OutputError::MissingData { index, outputs_data: data.len() })?
+            .unpack();
+        // witnesses are aligned with inputs, so an output cell carries none
+        Ok(T::try_from_arg(lock_args, type_args, data, None)?)
     }
 
     pub fn add_contract_cell_dep(&self, tx_builder: TransactionView, contract: &OutPoint) -> TransactionView {
         return tx_builder.as_advanced_builder().cell_dep(CellDep::new_builder().out_point(contract.clone()).build()
         ).build();
     }
+
+    ///
+    /// set the witness of input `input_index` to the witness of `cell_tx`,
+    /// missing witnesses before it are padded with empty bytes
+    pub fn set_witness(&self, tx_builder: TransactionView, input_index: usize, cell_tx: &dyn Cell) -> TransactionView {
+        let witness = cell_tx.get_witness().unwrap_or_default();
+        self.set_witness_bytes(tx_builder, input_index, Bytes::from(witness))
+    }
+
+    pub fn set_witness_bytes(&self, tx_builder: TransactionView, input_index: usize, witness: Bytes) -> TransactionView {
+        let mut witnesses: Vec<Bytes> = tx_builder.witnesses().into_iter()
+            .map(|witness| witness.unpack())
+            .collect();
+        if witnesses.len() <= input_index {
+            witnesses.resize(input_index + 1, Bytes::new());
+        }
+        witnesses[input_index] = witness;
+        tx_builder.as_advanced_builder()
+            .set_witnesses(witnesses.into_iter().map(|witness| witness.pack()).collect())
+            .build()
+    }
+
+    ///
+    /// set the witness of the first input in the script group of `script_hash`,
+    /// which is where `load_witness_args(0, Source::GroupInput)` reads from
+    pub fn set_group_witness(&self, tx_builder: TransactionView, script_hash: &Byte32, cell_tx: &dyn Cell) -> TransactionView {
+        let input_index = *self.group_input_indices(&tx_builder, script_hash)
+            .first()
+            .expect("no input in script group");
+        self.set_witness(tx_builder, input_index, cell_tx)
+    }
+
+    ///
+    /// indices of the inputs whose lock or type script hash is `script_hash`
+    pub fn group_input_indices(&self, tx_builder: &TransactionView, script_hash: &Byte32) -> Vec<usize> {
+        tx_builder.inputs().into_iter()
+            .enumerate()
+            .filter(|(_, input)| {
+                let (cell, _) = self.context.get_cell(&input.previous_output()).expect("input cell");
+                let type_hash = cell.type_().to_opt().map(|script| script.calc_script_hash());
+                cell.lock().calc_script_hash() == *script_hash || type_hash.as_ref() == Some(script_hash)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    ///
+    /// lock script hash of input `input_index`, for use with `set_group_witness`
+    pub fn input_lock_hash(&self, tx_builder: &TransactionView, input_index: usize) -> Byte32 {
+        let input = tx_builder.inputs().get(input_index).expect("input index");
+        let (cell, _) = self.context.get_cell(&input.previous_output()).expect("input cell");
+        cell.lock().calc_script_hash()
+    }
+
+    ///
+    /// pad the witnesses with empty bytes so that every input has one
+    pub fn pad_witnesses(&self, tx_builder: TransactionView) -> TransactionView {
+        let inputs_len = tx_builder.inputs().len();
+        if inputs_len == 0 || tx_builder.witnesses().len() >= inputs_len {
+            return tx_builder;
+        }
+        self.set_witness_bytes(tx_builder, inputs_len - 1, Bytes::new())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,135 @@
+use ckb_testtool::ckb_types::core::{TransactionBuilder, TransactionView};
+use ckb_testtool::ckb_types::bytes::Bytes;
+use ckb_testtool::ckb_types::packed::{Byte32, OutPoint};
+use crate::capacity::Capacity;
+use crate::cell_message::cell::Cell;
+use crate::chain::since::Since;
+use crate::ContractUtil;
+
+///
+/// Chainable transaction builder on top of `ContractUtil`.
+///
+/// The scenario owns the intermediate `TransactionView`, so a test can describe
+/// the transaction as a single expression:
+///
+/// ```ignore
+/// let tx = ct.tx()
+///     .input(&commitment_contract, None, &cell, Capacity::shannons(1000))
+///     .output(&always_success, None, &cell, Capacity::shannons(500))
+///     .dep(&auth_contract)
+///     .build();
+/// ```
+pub struct TxScenario<'a> {
+    ct: &'a mut ContractUtil,
+    tx: TransactionView,
+}
+
+impl<'a> TxScenario<'a> {
+    pub fn new(ct: &'a mut ContractUtil) -> Self {
+        Self::from_tx(ct, TransactionBuilder::default().build())
+    }
+
+    /// continue building on top of an existing transaction
+    pub fn from_tx(ct: &'a mut ContractUtil, tx: TransactionView) -> Self {
+        Self { ct, tx }
+    }
+
+    pub fn input(mut self, lock_contract: &OutPoint, type_contract: Option<&OutPoint>, cell_tx: &dyn Cell, capacity: Capacity) -> Self {
+        self.tx = self.ct.add_input(self.tx, lock_contract.clone(), type_contract.cloned(), cell_tx, capacity);
+        self
+    }
+
+    pub fn input_with_since(mut self, lock_contract: &OutPoint, type_contract: Option<&OutPoint>, cell_tx: &dyn Cell, since: Since, capacity: Capacity) -> Self {
+        self.tx = self.ct.add_input_with_since(self.tx, lock_contract.clone(), type_contract.cloned(), cell_tx, since, capacity);
+        self
+    }
+
+    /// spend an existing cell, e.g. an output of a committed tx
+    pub fn spend(self, out_point: &OutPoint) -> Self {
+        self.spend_with_since(out_point, Since::NONE)
+    }
+
+    pub fn spend_with_since(mut self, out_point: &OutPoint, since: Since) -> Self {
+        self.tx = self.ct.add_cell_input(self.tx, out_point, since);
+        self
+    }
+
+    pub fn output(mut self, lock_contract: &OutPoint, type_contract: Option<&OutPoint>, cell_tx: &dyn Cell, capacity: Capacity) -> Self {
+        self.tx = self.ct.add_outpoint(self.tx, lock_contract.clone(), type_contract.cloned(), cell_tx, capacity);
+        self
+    }
+
+    /// insert an output at `index`, shifting the following outputs
+    pub fn set_output(mut self, lock_contract: &OutPoint, type_contract: Option<&OutPoint>, cell_tx: &dyn Cell, capacity: Capacity, index: usize) -> Self {
+        self.tx = self.ct.set_output(self.tx, lock_contract.clone(), type_contract.cloned(), cell_tx, capacity, index);
+        self
+    }
+
+    pub fn replace_output(mut self, lock_contract: &OutPoint, type_contract: Option<&OutPoint>, cell_tx: &dyn Cell, capacity: Capacity, index: usize) -> Self {
+        self.tx = self.ct.replace_output(self.tx, lock_contract.clone(), type_contract.cloned(), cell_tx, capacity, index);
+        self
+    }
+
+    /// set the witness of input `input_index` from `cell_tx`
+    pub fn witness(mut self, input_index: usize, cell_tx: &dyn Cell) -> Self {
+        self.tx = self.ct.set_witness(self.tx, input_index, cell_tx);
+        self
+    }
+
+    pub fn witness_bytes(mut self, input_index: usize, witness: Bytes) -> Self {
+        self.tx = self.ct.set_witness_bytes(self.tx, input_index, witness);
+        self
+    }
+
+    /// set the witness of the first input in the script group of `script_hash`
+    pub fn group_witness(mut self, script_hash: &Byte32, cell_tx: &dyn Cell) -> Self {
+        self.tx = self.ct.set_group_witness(self.tx, script_hash, cell_tx);
+        self
+    }
+
+    /// give every input a witness, empty if none was set
+    pub fn pad_witnesses(mut self) -> Self {
+        self.tx = self.ct.pad_witnesses(self.tx);
+        self
+    }
+
+    pub fn dep(mut self, contract: &OutPoint) -> Self {
+        self.tx = self.ct.add_contract_cell_dep(self.tx, contract);
+        self
+    }
+
+    pub fn header_dep(mut self, block_hash: &Byte32) -> Self {
+        self.tx = self.ct.add_header_dep(self.tx, block_hash);
+        self
+    }
+
+    /// add the header of the chain tip to the header deps
+    pub fn tip_header_dep(self) -> Self {
+        let tip_hash = self.ct.clock.tip().hash();
+        self.header_dep(&tip_hash)
+    }
+
+    /// escape hatch for steps the scenario does not cover yet
+    pub fn map<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut ContractUtil, TransactionView) -> TransactionView,
+    {
+        self.tx = f(self.ct, self.tx);
+        self
+    }
+
+    /// the transaction built so far, without completing cell deps
+    pub fn tx(&self) -> &TransactionView {
+        &self.tx
+    }
+
+    /// complete the cell deps of all scripts and return the final transaction
+    pub fn build(self) -> TransactionView {
+        self.ct.context.complete_tx(self.tx)
+    }
+
+    /// return the transaction as is, useful for tests on incomplete transactions
+    pub fn build_raw(self) -> TransactionView {
+        self.tx
+    }
+}
@@ -0,0 +1,29 @@
+use crate::script_error::contract_errors;
+
+contract_errors! {
+    /// Exit codes of the commitment-lock contract.
+    pub enum CommitmentLockError {
+        /// the script panicked, e.g. on an arithmetic overflow
+        Panic = -1,
+        IndexOutOfBound = 1,
+        ItemMissing = 2,
+        LengthNotEnough = 3,
+        Encoding = 4,
+        MultipleInputs = 5,
+        InvalidSince = 6,
+        InvalidUnlockType = 7,
+        InvalidHtlcType = 8,
+        ArgsLenError = 9,
+        WitnessLenError = 10,
+        EmptyWitnessArgsError = 11,
+        WitnessHashError = 12,
+        OutputCapacityError = 13,
+        OutputLockError = 14,
+        OutputTypeError = 15,
+        OutputUdtAmountError = 16,
+        PreimageError = 17,
+        AuthError = 18,
+        /// ckb-auth `ERROR_MISMATCHED`, the signature does not match the pubkey hash
+        AuthMismatched = 101,
+    }
+}
@@ -0,0 +1,22 @@
+use crate::script_error::contract_errors;
+
+contract_errors! {
+    /// Exit codes of the funding-lock contract.
+    pub enum FundingLockError {
+        /// the script panicked, e.g. on a missing witness
+        Panic = -1,
+        IndexOutOfBound = 1,
+        ItemMissing = 2,
+        LengthNotEnough = 3,
+        Encoding = 4,
+        MultipleInputs = 5,
+        WitnessLenError = 6,
+        EmptyWitnessArgsError = 7,
+        FundingOutPointError = 8,
+        AuthError = 9,
+        /// ckb-auth `ERROR_MISMATCHED`, the signature verifies but blake160 of its pubkey is not the lock args
+        AuthMismatched = 101,
+        /// ckb-auth `ERROR_SCHNORR`, the aggregated signature does not verify
+        AuthSchnorrError = 110,
+    }
+}
@@ -0,0 +1,192 @@
+use std::fmt;
+use ckb_testtool::ckb_error::Error;
+use ckb_testtool::ckb_types::packed::Script;
+
+///
+/// Declares the error registry of a contract: a `#[repr(i8)]` enum of its exit codes,
+/// with `ContractError` implemented from the variants.
+/// ```ignore
+/// contract_errors! {
+///     /// Exit codes of the funding-lock contract.
+///     pub enum FundingLockError {
+///         Panic = -1,
+///         MultipleInputs = 5,
+///     }
+/// }
+/// ```
+macro_rules! contract_errors {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $code:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        #[repr(i8)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant = $code,)*
+        }
+
+        impl $crate::script_error::ContractError for $name {
+            fn code(&self) -> i8 {
+                *self as i8
+            }
+
+            fn from_code(code: i8) -> Option<Self> {
+                match code {
+                    $($code => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+
+            fn name(&self) -> String {
+                format!(concat!(stringify!($name), "::{:?}"), self)
+            }
+        }
+    };
+}
+
+pub(crate) use contract_errors;
+
+pub mod funding_lock;
+pub mod commitment_lock;
+
+///
+/// Error registry of a contract: maps its exit codes to symbolic names.
+///
+/// `i8` implements it as well, for exit codes that have no registry.
+pub trait ContractError: Copy {
+    fn code(&self) -> i8;
+    fn from_code(code: i8) -> Option<Self>;
+    /// symbolic name printed in assertion failures, e.g. `FundingLockError::MultipleInputs`
+    fn name(&self) -> String;
+}
+
+impl ContractError for i8 {
+    fn code(&self) -> i8 {
+        *self
+    }
+
+    fn from_code(code: i8) -> Option<Self> {
+        Some(code)
+    }
+
+    fn name(&self) -> String {
+        format!("code {}", self)
+    }
+}
+
+/// Which script of a cell a failure is reported for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptKind {
+    /// lock script of an input, `Inputs[i].Lock`
+    Lock,
+    /// type script whose group starts at an input, `Inputs[i].Type`
+    InputType,
+    /// type script whose group only has outputs, `Outputs[i].Type`
+    OutputType,
+}
+
+impl ScriptKind {
+    /// how CKB names the script group starting at `index`, e.g. `Inputs[0].Lock`
+    pub fn source(&self, index: usize) -> String {
+        match self {
+            ScriptKind::Lock => format!("Inputs[{}].Lock", index),
+            ScriptKind::InputType => format!("Inputs[{}].Type", index),
+            ScriptKind::OutputType => format!("Outputs[{}].Type", index),
+        }
+    }
+}
+
+///
+/// A script validation failure parsed from a `ckb_error::Error`.
+///
+/// CKB reports a failing script group as
+/// `TransactionScriptError { source: Inputs[0].Lock, cause: ValidationFailure: see error code 5 on page https://nervosnetwork.github.io/ckb-script-error-codes/by-type-hash/<code hash>.html#5 }`,
+/// where the index is the first input (or output) of the script group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptFailure {
+    pub kind: ScriptKind,
+    pub index: usize,
+    /// hex code hash of the failing script, as it appears in the error page url
+    pub code_hash: String,
+    /// `true` for `by-type-hash`, `false` for `by-data-hash`
+    pub by_type_hash: bool,
+    pub code: i8,
+}
+
+impl ScriptFailure {
+    pub fn parse(err: &Error) -> Option<Self> {
+        Self::parse_message(&err.to_string())
+    }
+
+    pub fn parse_message(message: &str) -> Option<Self> {
+        let source = after(message, "source: ")?;
+        let (kind, index) = parse_source(source)?;
+
+        let code = after(message, "error code ")?;
+        let code = code.split_whitespace().next()?.parse::<i8>().ok()?;
+
+        let page = after(message, "ckb-script-error-codes/by-")?;
+        let (by_type_hash, page) = if let Some(page) = page.strip_prefix("type-hash/") {
+            (true, page)
+        } else {
+            (false, page.strip_prefix("data-hash/")?)
+        };
+        let code_hash = page.split('.').next()?.to_string();
+
+        Some(ScriptFailure { kind, index, code_hash, by_type_hash, code })
+    }
+
+    /// whether the failure was reported for a script running `script`'s code
+    pub fn is_from(&self, script: &Script) -> bool {
+        format!("{:x}", script.code_hash()) == self.code_hash
+    }
+
+    pub fn matches(&self, kind: ScriptKind, index: usize, code: i8) -> bool {
+        self.kind == kind && self.index == index && self.code == code
+    }
+
+    /// the exit code looked up in the registry of `E`
+    pub fn error<E: ContractError>(&self) -> Option<E> {
+        E::from_code(self.code)
+    }
+
+    /// like `Display`, with the symbolic name of the exit code when `E` knows it
+    pub fn describe<E: ContractError>(&self) -> String {
+        match self.error::<E>() {
+            Some(error) => format!("{} [{}]", self, error.name()),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ScriptFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hash_type = if self.by_type_hash { "type" } else { "data" };
+        write!(f, "{} failed with code {} (code hash by {}: 0x{})", self.kind.source(self.index), self.code, hash_type, self.code_hash)
+    }
+}
+
+fn after<'a>(message: &'a str, pattern: &str) -> Option<&'a str> {
+    message.find(pattern).map(|start| &message[start + pattern.len()..])
+}
+
+// `Inputs[0].Lock`, `Inputs[1].Type` or `Outputs[0].Type`
+fn parse_source(source: &str) -> Option<(ScriptKind, usize)> {
+    let (is_input, rest) = if let Some(rest) = source.strip_prefix("Inputs[") {
+        (true, rest)
+    } else {
+        (false, source.strip_prefix("Outputs[")?)
+    };
+    let end = rest.find(']')?;
+    let index = rest[..end].parse::<usize>().ok()?;
+    let kind = match (is_input, &rest[end + 1..]) {
+        (true, group) if group.starts_with(".Lock") => ScriptKind::Lock,
+        (true, group) if group.starts_with(".Type") => ScriptKind::InputType,
+        (false, group) if group.starts_with(".Type") => ScriptKind::OutputType,
+        _ => return None,
+    };
+    Some((kind, index))
+}
@@ -0,0 +1,135 @@
+use serde_molecule::to_vec;
+use crate::capacity::Capacity;
+use crate::cell_message::cell::{Cell, WitnessArgsEncodings};
+use crate::cell_message::encoding::Encoding;
+use crate::cell_message::error::{CellField, CellOp, OutputError};
+use crate::cell_message::molecule_cell::MoleculeCell;
+use crate::cell_message::witness_args::EmptyWitnessArgs;
+use crate::cells::funding_lock::FundingCell;
+use crate::cells::xudt_data::{XUDTData, XUDTDataCell};
+use crate::ContractUtil;
+
+// no encodings field: encodings come from the attributes, struct by default
+#[derive(Cell, PartialEq, Debug)]
+struct RenamedCell {
+    #[cell(lock_arg)]
+    pub owner: [u8; 20],
+    #[cell(type_arg)]
+    pub token: Option<[u8; 32]>,
+    #[cell(data)]
+    pub amount: u128,
+    #[cell(witness, encoding = "table")]
+    pub proof: Option<EmptyWitnessArgs>,
+}
+
+impl RenamedCell {
+    fn default() -> Self {
+        RenamedCell {
+            owner: [0; 20],
+            token: None,
+            amount: 0,
+            proof: None,
+        }
+    }
+}
+
+#[test]
+fn test_derive_with_attributes() {
+    let cell = RenamedCell {
+        owner: [3; 20],
+        token: Some([4; 32]),
+        amount: 1000,
+        proof: Some(EmptyWitnessArgs::default()),
+    };
+    assert_eq!(cell.get_lock_arg(), [3u8; 20].to_vec());
+    assert_eq!(cell.get_type_arg(), Some([4u8; 32].to_vec()));
+    assert_eq!(cell.get_data(), 1000u128.to_le_bytes().to_vec());
+    assert_eq!(cell.get_witness(), Some(to_vec(&EmptyWitnessArgs::default(), false).unwrap()));
+
+    let decoded = RenamedCell::from_arg(cell.get_lock_arg(), cell.get_type_arg(), cell.get_data(), cell.get_witness());
+    assert_eq!(decoded, cell);
+}
+
+#[test]
+fn test_derive_generic_cell() {
+    let mut cell = FundingCell::default();
+    cell.lock_arg = [9; 20];
+    cell.type_arg = Some(1);
+    cell.data = 2;
+    assert_eq!(cell.get_lock_arg(), [9u8; 20].to_vec());
+    assert_eq!(cell.get_type_arg(), Some(vec![1]));
+    assert_eq!(cell.get_data(), vec![2]);
+    assert_eq!(cell.get_witness(), None);
+
+    let decoded = FundingCell::from_arg(cell.get_lock_arg(), cell.get_type_arg(), cell.get_data(), cell.get_witness());
+    assert_eq!(decoded.lock_arg, cell.lock_arg);
+    assert_eq!(decoded.type_arg, cell.type_arg);
+    assert_eq!(decoded.data, cell.data);
+    assert_eq!(decoded.witness, None);
+}
+
+#[test]
+fn test_try_from_arg_reports_field() {
+    let cell = FundingCell::default();
+    let err = FundingCell::try_from_arg(vec![0; 19], None, cell.get_data(), None).err().unwrap();
+    assert_eq!(err.field, CellField::LockArg);
+    assert_eq!(err.op, CellOp::Decode);
+    assert_eq!(err.len, 19);
+    assert_eq!(err.offset, Some(19));
+
+    // a WitnessArgs table whose total size is wrong
+    let xudt = XUDTDataCell::new([1; 32], XUDTData { amount: 1 });
+    let mut witness = xudt.get_witness().unwrap();
+    witness[0] = 0xff;
+    let err = XUDTDataCell::try_from_arg(xudt.get_lock_arg(), xudt.get_type_arg(), xudt.get_data(), Some(witness)).err().unwrap();
+    assert_eq!(err.field, CellField::Witness);
+    assert_eq!(err.encoding, Encoding::Table);
+    assert_eq!(err.offset, Some(0));
+}
+
+#[test]
+fn test_get_cell_by_index_error() {
+    let mut ct = ContractUtil::new();
+    let always_success = ct.alway_contract.clone();
+    let xudt = XUDTDataCell::new([1; 32], XUDTData { amount: 1 });
+    let tx = ct.tx().output(&always_success, None, &xudt, Capacity::shannons(100)).build_raw();
+
+    let decoded: XUDTDataCell = ct.get_cell_by_index(tx.clone(), 0).unwrap();
+    assert_eq!(decoded.data, xudt.data);
+
+    let err = match ct.get_cell_by_index::<FundingCell>(tx.clone(), 0) {
+        Err(OutputError::Cell(err)) => err,
+        result => panic!("expected a cell error, got {:?}", result),
+    };
+    assert_eq!((err.field, err.op), (CellField::LockArg, CellOp::Decode));
+    assert_eq!(err.offset, Some(1));
+    println!("{}", err);
+
+    let err = ct.get_cell_by_index::<XUDTDataCell>(tx, 1).err().unwrap();
+    assert_eq!(err, OutputError::Missing { index: 1, outputs: 1 });
+    assert_eq!(err.to_string(), "tx has 1 outputs, no output 1");
+}
+
+#[test]
+fn test_molecule_cell_alias() {
+    type UdtCell = MoleculeCell<[u8; 20], [u8; 32], u128, [u8; 8]>;
+    let cell = UdtCell::new([5; 20], Some([6; 32]), 1000, Some([7; 8]));
+    assert_eq!(cell.get_lock_arg(), [5u8; 20].to_vec());
+    assert_eq!(cell.get_data(), 1000u128.to_le_bytes().to_vec());
+    assert_eq!(cell.get_witness(), Some([7u8; 8].to_vec()));
+
+    let decoded = UdtCell::from_arg(cell.get_lock_arg(), cell.get_type_arg(), cell.get_data(), cell.get_witness());
+    assert_eq!(decoded, cell);
+    assert_eq!(UdtCell::default(), UdtCell::new([0; 20], None, 0, None));
+}
+
+#[test]
+fn test_molecule_cell_alias_encodings() {
+    // decoding starts from no value, the table witness comes from the type
+    type WitnessArgsCell = MoleculeCell<[u8; 20], u8, u8, EmptyWitnessArgs, WitnessArgsEncodings>;
+    let cell = WitnessArgsCell::new([5; 20], None, 0, Some(EmptyWitnessArgs::default()));
+    assert_eq!(cell.get_witness(), Some(to_vec(&EmptyWitnessArgs::default(), false).unwrap()));
+
+    let decoded = WitnessArgsCell::from_arg(cell.get_lock_arg(), cell.get_type_arg(), cell.get_data(), cell.get_witness());
+    assert_eq!(decoded, cell);
+}
@@ -0,0 +1,123 @@
+use ckb_testtool::ckb_types::prelude::Unpack;
+use crate::capacity::Capacity;
+use crate::chain::consensus::ConsensusError;
+use crate::chain::clock::GENESIS_TIMESTAMP;
+use crate::chain::ledger::LedgerError;
+use crate::chain::since::Since;
+use crate::fiber::channel::Channel;
+use crate::fiber::commitment::{HashAlgorithm, HtlcType};
+use crate::fiber::settlement::Unlock;
+use crate::prelude::SHANNONS_PER_CKB;
+use crate::ContractUtil;
+
+const MAX_CYCLES: u64 = 100_000_000;
+const CAPACITY: Capacity = Capacity::ckb(1000);
+
+fn open_channel(ct: &mut ContractUtil) -> Channel {
+    Channel::open(ct, 2, CAPACITY, Since::relative_epoch(10, 1, 2)).unwrap()
+}
+
+#[test]
+fn test_channel_open() {
+    let mut ct = ContractUtil::new();
+    let channel = open_channel(&mut ct);
+    assert!(ct.ledger.is_live(&channel.funding));
+    let funding = ct.context.get_cell(&channel.funding).unwrap().0;
+    let capacity: u64 = funding.capacity().unpack();
+    assert_eq!(capacity, CAPACITY.as_u64());
+    assert_eq!(funding.lock().args().raw_data().as_ref(), channel.signer.lock_arg().as_slice());
+}
+
+#[test]
+fn test_channel_revocation() {
+    let mut ct = ContractUtil::new();
+    let channel = open_channel(&mut ct);
+    let commitment = channel.commit(&mut ct, Vec::new()).unwrap();
+    assert!(!ct.ledger.is_live(&channel.funding));
+
+    assert!(channel.settle(&mut ct, &commitment, &Unlock::Revoke).unwrap().is_none());
+    assert!(!ct.ledger.is_live(&commitment.out_point));
+}
+
+#[test]
+fn test_channel_local_delay() {
+    let mut ct = ContractUtil::new();
+    let channel = open_channel(&mut ct);
+    let commitment = channel.commit(&mut ct, Vec::new()).unwrap();
+
+    let (settlement, tx) = channel.settlement_tx(&mut ct, &commitment, &Unlock::LocalDelay);
+    assert_eq!(settlement.since, channel.local_delay);
+    assert!(matches!(ct.commit_strict_tx(&tx, MAX_CYCLES), Err(LedgerError::ImmatureInput(_))));
+    assert!(ct.ledger.is_live(&commitment.out_point));
+    ct.advance_epochs(10, 1, 2);
+    ct.commit_strict_tx(&tx, MAX_CYCLES).unwrap();
+}
+
+// the funding cell goes to one commitment only, the older one can't be committed after the newer
+#[test]
+fn test_channel_conflicting_commitments() {
+    let mut ct = ContractUtil::new();
+    let mut channel = open_channel(&mut ct);
+    let old = channel.commitment_tx(&mut ct, Vec::new());
+    let htlc = channel.htlc(HtlcType::OFFERED, 100 * SHANNONS_PER_CKB as u128, &[42; 32], Since::absolute_timestamp(GENESIS_TIMESTAMP + 3600));
+    let new = channel.commit(&mut ct, vec![htlc]).unwrap();
+
+    assert_eq!(ct.commit_strict_tx(&old.tx, MAX_CYCLES), Err(LedgerError::DeadInput { index: 0, spent_by: new.tx.hash() }));
+    channel.settle(&mut ct, &new, &Unlock::Revoke).unwrap();
+    assert!(matches!(channel.settle(&mut ct, &new, &Unlock::LocalDelay), Err(LedgerError::DeadInput { .. })));
+}
+
+// an offered htlc claimed with its preimage, then a received one timed out, then the rest after the local delay
+#[test]
+fn test_channel_htlc_settlements() {
+    let mut ct = ContractUtil::new();
+    let mut channel = open_channel(&mut ct);
+    let preimage = [42; 32];
+    let expiry = Since::absolute_timestamp(GENESIS_TIMESTAMP + 3600);
+    let offered = channel.htlc(HtlcType::OFFERED, 100 * SHANNONS_PER_CKB as u128, &preimage, expiry);
+    let received = channel.htlc(HtlcType::RECEIVED.with_hash_algorithm(HashAlgorithm::Sha256), 200 * SHANNONS_PER_CKB as u128, &[24; 32], expiry);
+    let commitment = channel.commit(&mut ct, vec![offered, received.clone()]).unwrap();
+
+    let commitment = channel.settle(&mut ct, &commitment, &Unlock::HtlcPreimage { index: 0, preimage }).unwrap().unwrap();
+    assert_eq!(commitment.state.capacity, CAPACITY - Capacity::ckb(100));
+    assert_eq!(commitment.state.script.pending_htlcs, vec![received]);
+
+    let result = channel.settle(&mut ct, &commitment, &Unlock::HtlcTimeout { index: 0 });
+    assert!(matches!(result, Err(LedgerError::ImmatureInput(_))));
+    ct.advance_seconds(3600);
+    let commitment = channel.settle(&mut ct, &commitment, &Unlock::HtlcTimeout { index: 0 }).unwrap().unwrap();
+    assert_eq!(commitment.state.capacity, CAPACITY - Capacity::ckb(300));
+    assert!(commitment.state.script.pending_htlcs.is_empty());
+
+    let result = channel.settle(&mut ct, &commitment, &Unlock::LocalDelay);
+    assert!(matches!(result, Err(LedgerError::ImmatureInput(_))));
+    ct.advance_epochs(10, 1, 2);
+    assert!(channel.settle(&mut ct, &commitment, &Unlock::LocalDelay).unwrap().is_none());
+}
+
+// the preimage of another htlc doesn't settle this one
+#[test]
+fn test_channel_wrong_preimage() {
+    let mut ct = ContractUtil::new();
+    let mut channel = open_channel(&mut ct);
+    let htlc = channel.htlc(HtlcType::OFFERED, 100 * SHANNONS_PER_CKB as u128, &[42; 32], Since::absolute_timestamp(GENESIS_TIMESTAMP + 3600));
+    let commitment = channel.commit(&mut ct, vec![htlc]).unwrap();
+
+    let result = channel.settle(&mut ct, &commitment, &Unlock::HtlcPreimage { index: 0, preimage: [24; 32] });
+    assert!(matches!(result, Err(LedgerError::Verification(_))));
+    assert!(ct.ledger.is_live(&commitment.out_point));
+}
+
+// the payment goes to a wallet cell, a node rejects one holding less than the cell occupies
+#[test]
+fn test_channel_htlc_below_occupied_capacity() {
+    let mut ct = ContractUtil::new();
+    let mut channel = open_channel(&mut ct);
+    let preimage = [42; 32];
+    let htlc = channel.htlc(HtlcType::OFFERED, 5 * SHANNONS_PER_CKB as u128, &preimage, Since::absolute_timestamp(GENESIS_TIMESTAMP + 3600));
+    let commitment = channel.commit(&mut ct, vec![htlc]).unwrap();
+
+    let result = channel.settle(&mut ct, &commitment, &Unlock::HtlcPreimage { index: 0, preimage });
+    assert!(matches!(result, Err(LedgerError::Consensus(ConsensusError::InsufficientCellCapacity { index: 1, .. }))));
+    assert!(ct.ledger.is_live(&commitment.out_point));
+}
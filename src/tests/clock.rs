@@ -0,0 +1,96 @@
+use ckb_testtool::ckb_types::core::TransactionView;
+use ckb_testtool::ckb_types::prelude::Unpack;
+use crate::capacity::Capacity;
+use crate::cells::demo::Demo;
+use crate::chain::clock::{DEFAULT_BLOCK_INTERVAL, DEFAULT_EPOCH_LENGTH, GENESIS_TIMESTAMP};
+use crate::chain::since::Since;
+use crate::ContractUtil;
+use crate::prelude::ContextExt;
+
+#[test]
+fn test_clock_advance() {
+    let mut ct = ContractUtil::new();
+    assert_eq!(ct.clock.tip().number(), 0);
+    assert_eq!(ct.clock.timestamp(), GENESIS_TIMESTAMP);
+
+    let header = ct.advance_blocks(10);
+    assert_eq!(header.number(), 10);
+    assert_eq!(header.timestamp(), (GENESIS_TIMESTAMP + 10 * DEFAULT_BLOCK_INTERVAL) * 1000);
+
+    // half an epoch, then a whole one
+    let header = ct.advance_epochs(1, 1, 2);
+    assert_eq!(header.number(), 10 + DEFAULT_EPOCH_LENGTH + DEFAULT_EPOCH_LENGTH / 2);
+    assert_eq!((header.epoch().number(), header.epoch().index()), (1, 10 + DEFAULT_EPOCH_LENGTH / 2));
+
+    // rounded up to whole blocks
+    let number = ct.clock.tip().number();
+    let header = ct.advance_seconds(DEFAULT_BLOCK_INTERVAL + 1);
+    assert_eq!(header.number(), number + 2);
+    assert_eq!(ct.clock.tip().hash(), header.hash());
+}
+
+fn since_tx(ct: &mut ContractUtil, since: Since) -> TransactionView {
+    let always_success = ct.alway_contract.clone();
+    ct.tx()
+        .input_with_since(&always_success, None, &Demo::default(), since, Capacity::shannons(1000))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .build()
+}
+
+#[test]
+fn test_relative_since_maturity() {
+    for (since, before, after) in [
+        (Since::relative_block_number(100), 99, 1),
+        (Since::relative_epoch(1, 1, 2), DEFAULT_EPOCH_LENGTH + DEFAULT_EPOCH_LENGTH / 2 - 1, 1),
+        (Since::relative_timestamp(3600), 3600 / DEFAULT_BLOCK_INTERVAL - 1, 1),
+    ] {
+        let mut ct = ContractUtil::new();
+        // committed after genesis, relative values count from there
+        ct.advance_blocks(7);
+        let tx = since_tx(&mut ct, since);
+        assert!(ct.check_since(&tx).is_err());
+        ct.advance_blocks(before);
+        assert!(ct.check_since(&tx).is_err(), "{} mature too early", since);
+        ct.advance_blocks(after);
+        ct.check_since(&tx).unwrap();
+        ct.context.should_be_passed(&tx, 10_000_000).unwrap();
+    }
+}
+
+#[test]
+fn test_absolute_since_maturity() {
+    let mut ct = ContractUtil::new();
+    let tx = since_tx(&mut ct, Since::absolute_timestamp(GENESIS_TIMESTAMP + 3600));
+    ct.advance_seconds(3600 - DEFAULT_BLOCK_INTERVAL);
+    assert!(ct.check_since(&tx).is_err());
+    ct.advance_blocks(1);
+    ct.check_since(&tx).unwrap();
+
+    let tx = since_tx(&mut ct, Since::absolute_block_number(1000));
+    assert!(ct.check_since(&tx).is_err());
+    ct.advance_blocks(1000);
+    ct.check_since(&tx).unwrap();
+
+    // not checked by the constructor, but by the chain
+    let tx = since_tx(&mut ct, Since::raw(1 << 56));
+    assert!(ct.check_since(&tx).unwrap_err().contains("reserved"));
+}
+
+#[test]
+fn test_header_deps() {
+    let mut ct = ContractUtil::new();
+    let header = ct.advance_blocks(5);
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .tip_header_dep()
+        .build();
+    assert_eq!(tx.header_deps().get(0).unwrap(), header.hash());
+    ct.context.should_be_passed(&tx, 10_000_000).unwrap();
+
+    let input = tx.inputs().get(0).unwrap();
+    assert_eq!(ct.clock.committed(&input.previous_output()).unwrap().number(), 5);
+    let since: u64 = input.since().unpack();
+    assert_eq!(since, 0);
+}
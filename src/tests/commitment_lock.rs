@@ -1,20 +1,23 @@
-use ckb_std::since::{EpochNumberWithFraction, Since};
 use ckb_testtool::{
-    ckb_crypto::secp::Generator,
-    ckb_hash::blake2b_256,
-    ckb_types::{core::TransactionBuilder, prelude::*},
+    ckb_crypto::secp::{Generator, Privkey, Pubkey},
+    ckb_types::core::TransactionView,
 };
 
-use ckb_testtool::ckb_types::prelude::{Builder, Entity};
-use sha2::{Digest, Sha256};
-use crate::cell_message::cell::MoleculeStructFlag;
-use crate::cells::commitment_lock::{CommitmentArgErrCell, CommitmentCellNoHtlcAndPreImage, CommitmentHTCL1Cell, CommitmentHTCL1WithPreimageAndUDTCell, CommitmentHTCL2Cell, CommitmentHTCL2WithPriImageAndUDTCell, CommitmentMaxErrLenWitness, CommitmentMaxWitnessLenErrCell, CommitmentMinErrLenWitness, CommitmentMinWitnessLenErrCell, CommitmentPendinghtlc1WithPreimageWitness, CommitmentPendinghtlc1Witness, CommitmentPendinghtlc2WithPriImageWitness, CommitmentPendinghtlc2Witness, CommitmentWitness, CommitmentWitnessNoHtlcAndPreImage, PendingHtlc};
+use crate::capacity::Capacity;
+use crate::cell_message::cell::Cell;
+use crate::cell_message::error::{CellField, CellOp};
+use crate::cell_message::malformed::MalformedCell;
+use crate::cell_message::witness_args::EmptyWitnessArgs;
+use crate::cells::commitment_lock::{CommitmentCell, CommitmentUdtCell, CommitmentWitness, PendingHtlc};
 use crate::{ContractUtil};
+use crate::chain::since::{Since, SinceMetric};
+use crate::fiber::blake160;
+use crate::fiber::commitment::{pubkey_hash, sign, CommitmentScript, HashAlgorithm, HtlcDirection, HtlcType, UnlockType};
+use crate::fiber::settlement::{CommitmentState, Settlement, Unlock};
+use crate::prelude::{ContextExt, ScriptKind, SHANNONS_PER_CKB};
+use crate::script_error::commitment_lock::CommitmentLockError;
 const MAX_CYCLES: u64 = 10_000_000;
 
-const EMPTY_WITNESS_ARGS: [u8; 16] = [16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0];
-const BYTE_SHANNONS: u64 = 100_000_000;
-
 #[test]
 fn test_01() {
     let mut ct = ContractUtil::new();
@@ -24,45 +27,29 @@ fn test_01() {
     // prepare script
     let mut generator = Generator::new();
     // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
 
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-    ].concat();
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1);
 
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
+    let args = script.lock_arg();
 
-    let mut commitment_cell = CommitmentCellNoHtlcAndPreImage::default();
-    commitment_cell.lock_arg = <[u8; 20]>::try_from(args).unwrap();
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
+    let mut commitment_cell = CommitmentCell::default();
+    commitment_cell.lock_arg = args;
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
 
     // sign with revocation key
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-
-    let signature = revocation_key
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-    commitment_cell.witness = Some(CommitmentWitnessNoHtlcAndPreImage {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        unlock_type: 255,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-    });
-    let tx = ct.replace_output(tx, ct.alway_contract.clone(), None, &commitment_cell, 500, 0);
+    let signature = sign(&revocation_key.0, &tx);
+
+    commitment_cell.witness = Some(script.witness(UnlockType::Revocation, signature, None));
+    let tx = ct.set_witness(tx, 0, &commitment_cell);
 
     println!("tx: {:?}", tx);
 
@@ -74,1125 +61,661 @@ fn test_01() {
 }
 
 #[test]
-fn test_multiple_inputs_err() {
-    let mut ct = ContractUtil::new();
-    let commitment_contract = ct.deploy_contract("commitment-lock");
-    let auth_contract = ct.deploy_contract("auth");
-    let mut commitment_cell = CommitmentCellNoHtlcAndPreImage::default();
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-
-    println!("tx: {:?}", tx);
-
-    // run
-    match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            assert!(err.to_string().contains("code 5"))
-        }
-    };
-}
-
-#[test]
-fn test_invalid_since_err() {
-    // InvalidSince
-    let mut ct = ContractUtil::new();
-    let commitment_contract = ct.deploy_contract("commitment-lock");
-    let auth_contract = ct.deploy_contract("auth");
-
-    // prepare script
+fn test_commitment_script() {
     let mut generator = Generator::new();
-    // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
+    let remote_htlc_key = generator.gen_keypair();
+    let local_htlc_key = generator.gen_keypair();
+    let expiry = Since::absolute_timestamp(1711976400);
 
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1)
+        .with_htlc(PendingHtlc::new(HtlcType::RECEIVED, 1000, blake160([42u8; 32]), &remote_htlc_key.1, &local_htlc_key.1, expiry.as_u64()));
     let witness_script = [
         local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
+        pubkey_hash(&local_delay_epoch_key.1).to_vec(),
+        pubkey_hash(&revocation_key.1).to_vec(),
+        [0b00000001].to_vec(),
+        1000u128.to_le_bytes().to_vec(),
+        blake160([42u8; 32]).to_vec(),
+        pubkey_hash(&remote_htlc_key.1).to_vec(),
+        pubkey_hash(&local_htlc_key.1).to_vec(),
+        expiry.as_u64().to_le_bytes().to_vec(),
     ].concat();
+    assert_eq!(script.witness_script(), witness_script);
+    assert_eq!(script.lock_arg(), blake160(&witness_script));
+
+    let witness = script.witness(UnlockType::Htlc(0), [1; 65], Some([42; 32]));
+    assert_eq!(witness.witness_args, EmptyWitnessArgs::default());
+    assert_eq!(witness.revocation_pubkey_hash, pubkey_hash(&revocation_key.1));
+    assert_eq!(witness.pending_htlc, script.pending_htlcs);
+    assert_eq!(witness.preimage, Some([42; 32]));
+}
 
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-
-    let mut commitment_cell = CommitmentCellNoHtlcAndPreImage::default();
-    commitment_cell.lock_arg = <[u8; 20]>::try_from(args).unwrap();
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input_with_since(tx, commitment_contract.clone(), None, &commitment_cell, Since::from_epoch(EpochNumberWithFraction::new(10, 0, 2), false).as_u64(), 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-
-    // sign with revocation key
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-
-    let signature = revocation_key
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-    commitment_cell.witness = Some(CommitmentWitnessNoHtlcAndPreImage {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        unlock_type: 255,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-    });
-    let tx = ct.replace_output(tx, ct.alway_contract.clone(), None, &commitment_cell, 500, 0);
-
-    println!("tx: {:?}", tx);
+// `count` htlcs with keys of their own
+fn pending_htlcs(generator: &mut Generator, count: usize) -> Vec<PendingHtlc> {
+    (0..count).map(|index| {
+        let remote_htlc_key = generator.gen_keypair();
+        let local_htlc_key = generator.gen_keypair();
+        let expiry = Since::absolute_timestamp(1711976400 + index as u64 * 3600);
+        PendingHtlc::new(HtlcType::from((index % 2) as u8), 1000 + index as u128, blake160((index as u64).to_le_bytes()), &remote_htlc_key.1, &local_htlc_key.1, expiry.as_u64())
+    }).collect()
+}
 
-    // run
-    match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 6"))
+#[test]
+fn test_commitment_witness_bytes() {
+    let mut generator = Generator::new();
+    let local_delay_epoch_key = generator.gen_keypair();
+    let revocation_key = generator.gen_keypair();
+    for count in [0, 1, 3] {
+        for preimage in [None, Some([42; 32])] {
+            let mut script = CommitmentScript::new(0, &local_delay_epoch_key.1, &revocation_key.1);
+            script.pending_htlcs = pending_htlcs(&mut generator, count);
+            let witness = script.witness(UnlockType::Revocation, [1; 65], preimage);
+
+            let bytes = witness.to_bytes().unwrap();
+            assert_eq!(bytes.len(), CommitmentWitness::len_with(count, preimage.is_some()));
+            assert_eq!(bytes[16..CommitmentWitness::PREFIX_LEN + count * CommitmentWitness::PENDING_HTLC_LEN], script.witness_script());
+            assert_eq!(CommitmentWitness::from_bytes(&bytes).unwrap(), witness);
+            assert!(CommitmentWitness::from_bytes(&bytes[1..]).is_err());
         }
-    };
+    }
 }
 
-#[test]
-fn test_invalid_unlock_type_err() {
-    let mut ct = ContractUtil::new();
+// a cell locked by `count` htlcs and a revocation unlock of it
+fn revocation_unlock(ct: &mut ContractUtil, count: usize) -> (CommitmentCell, TransactionView) {
     let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
 
-    // prepare script
     let mut generator = Generator::new();
-    // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
+    let mut script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1);
+    script.pending_htlcs = pending_htlcs(&mut generator, count);
+
+    let mut cell = CommitmentCell::new(script.lock_arg(), None, 0, None);
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &cell, Capacity::shannons(1000))
+        .output(&always_success, None, &cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
+    cell.witness = Some(script.witness(UnlockType::Revocation, sign(&revocation_key.0, &tx), None));
+    let tx = ct.set_witness(tx, 0, &cell);
+    (cell, tx)
+}
 
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-    ].concat();
-
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-
-    let mut commitment_cell = CommitmentCellNoHtlcAndPreImage::default();
-    commitment_cell.lock_arg = <[u8; 20]>::try_from(args).unwrap();
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-
-    // sign with revocation key
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-
-    let signature = revocation_key
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-    commitment_cell.witness = Some(CommitmentWitnessNoHtlcAndPreImage {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        unlock_type: 254,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-    });
-    let tx = ct.replace_output(tx, ct.alway_contract.clone(), None, &commitment_cell, 500, 0);
-
-    println!("tx: {:?}", tx);
-
-    // run
-    match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            assert!(err.to_string().contains("code 7"))
-        }
+#[test]
+fn test_revocation_with_n_htlcs() {
+    for count in [0, 1, 3, 16, 64] {
+        let mut ct = ContractUtil::new();
+        let (_, tx) = revocation_unlock(&mut ct, count);
+        let cycles = ct.context.should_be_passed(&tx, 100000000).unwrap();
+        println!("{} htlcs consume cycles: {}", count, cycles);
     }
 }
 
 #[test]
-fn test_invalid_htlc_type_err() {
-    println!("skip")
+fn test_htlc_index_out_of_range() {
+    let mut ct = ContractUtil::new();
+    let (mut cell, tx) = revocation_unlock(&mut ct, 3);
+    // htlcs are numbered 0..3
+    cell.witness.as_mut().unwrap().unlock_type = UnlockType::Htlc(3);
+    let tx = ct.set_witness(tx, 0, &cell);
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::InvalidUnlockType);
 }
 
 #[test]
-fn test_args_len_error() {
+fn test_witness_len_with_n_htlcs() {
     let mut ct = ContractUtil::new();
-    let commitment_contract = ct.deploy_contract("commitment-lock");
-    let auth_contract = ct.deploy_contract("auth");
-    let commitment_cell = CommitmentArgErrCell::default();
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-
-    println!("tx: {:?}", tx);
+    let (cell, tx) = revocation_unlock(&mut ct, 3);
+
+    for malformed in [
+        MalformedCell::from_cell(&cell).extend(CellField::Witness, &[0]),
+        MalformedCell::from_cell(&cell).truncate(CellField::Witness, 1),
+        // not a whole htlc less
+        MalformedCell::from_cell(&cell).truncate(CellField::Witness, CommitmentWitness::PENDING_HTLC_LEN - 1),
+    ] {
+        let tx = ct.set_witness(tx.clone(), 0, &malformed);
+        ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::WitnessLenError);
+    }
 
-    // run
-    match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 9"))
-        }
-    };
+    // one more htlc than the args were hashed from
+    let mut witness = cell.witness.clone().unwrap();
+    witness.pending_htlc.push(witness.pending_htlc[0].clone());
+    let tx = ct.set_witness(tx, 0, &CommitmentCell { witness: Some(witness), ..cell });
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::WitnessHashError);
 }
 
+// the unlock type indexes 255 htlcs at most, 0xFF being the revocation and local delay unlocks
 #[test]
-fn test_witness_len_err_too_min() {
+fn test_witness_len_limits() {
     let mut ct = ContractUtil::new();
-    let commitment_contract = ct.deploy_contract("commitment-lock");
-    let auth_contract = ct.deploy_contract("auth");
-    let mut commitment_cell = CommitmentMinWitnessLenErrCell::default();
-    commitment_cell.witness = Some(CommitmentMinErrLenWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: 0,
-        local_delay_pubkey_hash: [1; 20],
-        revocation_pubkey_hash: [1; 20],
-        signature: [1; 65],
-    });
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    println!("tx: {:?}", tx);
+    let (cell, tx) = revocation_unlock(&mut ct, 255);
+    ct.context.should_be_passed(&tx, 100000000).unwrap();
+
+    // trailing bytes past the last htlc, neither a whole htlc nor a preimage
+    let malformed = MalformedCell::from_cell(&cell).extend(CellField::Witness, &[0; CommitmentWitness::PENDING_HTLC_LEN + 1]);
+    let tx = ct.set_witness(tx, 0, &malformed);
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::WitnessLenError);
+
+    // twice the htlcs the args were hashed from
+    let mut witness = cell.witness.clone().unwrap();
+    witness.pending_htlc.extend(witness.pending_htlc.clone());
+    assert_eq!(witness.to_bytes().unwrap().len(), CommitmentWitness::len_with(510, false));
+    let tx = ct.set_witness(tx, 0, &CommitmentCell { witness: Some(witness), ..cell });
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::WitnessHashError);
+}
 
-    // run
-    match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 10"))
-        }
+// a commitment cell with an offered htlc and a received htlc, and the keys of its script
+fn settlement_state(generator: &mut Generator, udt: bool) -> (CommitmentState, Vec<(Privkey, Pubkey)>) {
+    let keys: Vec<(Privkey, Pubkey)> = (0..6).map(|_| generator.gen_keypair()).collect();
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
+    // timeout after 2024-04-01 01:00:00 and 2024-04-02 01:00:00
+    let expiry1 = Since::absolute_timestamp(1711976400);
+    let expiry2 = Since::absolute_timestamp(1712062800);
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &keys[0].1, &keys[1].1)
+        .with_htlc(PendingHtlc::new(HtlcType::OFFERED, 1234567890, blake160([42u8; 32]), &keys[2].1, &keys[3].1, expiry1.as_u64()))
+        .with_htlc(PendingHtlc::new(HtlcType::RECEIVED, 9876543210, blake160([24u8; 32]), &keys[4].1, &keys[5].1, expiry2.as_u64()));
+    let state = if udt {
+        CommitmentState::udt(script, Capacity::shannons(1000), [42; 32], 424242424242424242)
+    } else {
+        CommitmentState::ckb(script, Capacity::ckb(100))
     };
+    (state, keys)
 }
 
-#[test]
-fn test_witness_len_err_too_big() {
-    let mut ct = ContractUtil::new();
+// spends `state` as `settlement` says into `output`, signed by the key it asks for
+fn settlement_tx(ct: &mut ContractUtil, state: &CommitmentState, settlement: &Settlement, output: Option<&CommitmentState>, keys: &[(Privkey, Pubkey)]) -> TransactionView {
     let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-    let mut commitment_cell = CommitmentMaxWitnessLenErrCell::default();
-    commitment_cell.witness = Some(CommitmentMaxErrLenWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: 0,
-        local_delay_pubkey_hash: [1; 20],
-        revocation_pubkey_hash: [1; 20],
-        unlock_type: 0,
-        signature: [1; 65],
-        err: [1; 5],
-    });
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    println!("tx: {:?}", tx);
+    let udt_contract = ct.deploy_contract("simple_udt");
+    let type_contract = state.udt.map(|_| &udt_contract);
+    let always_success = ct.alway_contract.clone();
+
+    let scenario = ct.tx()
+        .input_with_since(&commitment_contract, type_contract, &state.cell(), settlement.since, state.capacity);
+    let tx = match output {
+        Some(output) => scenario.output(&commitment_contract, type_contract, &output.cell(), output.capacity),
+        // the signer takes the whole cell
+        None => scenario.output(&always_success, None, &state.cell(), state.capacity),
+    }.dep(&auth_contract).build();
+
+    let key = keys.iter().find(|key| pubkey_hash(&key.1) == settlement.signer).expect("signer key");
+    let cell = CommitmentUdtCell::new(state.script.lock_arg(), None, 0, Some(settlement.witness(sign(&key.0, &tx))));
+    ct.set_witness(tx, 0, &cell)
+}
 
-    // run
-    match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 10"))
+#[test]
+fn test_settlement_model() {
+    let mut generator = Generator::new();
+    let (state, _) = settlement_state(&mut generator, true);
+    let htlcs = &state.script.pending_htlcs;
+
+    let settlement = state.settle(&Unlock::Revoke);
+    assert_eq!((settlement.unlock_type, settlement.signer, settlement.since), (UnlockType::Revocation, state.script.revocation_pubkey_hash, Since::NONE));
+    assert_eq!(settlement.output, None);
+    let settlement = state.settle(&Unlock::LocalDelay);
+    assert_eq!((settlement.signer, settlement.since), (state.script.local_delay_pubkey_hash, Since::relative_epoch(10, 1, 2)));
+
+    // the offered htlc is claimed by the remote key, the received one by the local key
+    let settlement = state.settle(&Unlock::HtlcPreimage { index: 0, preimage: [42; 32] });
+    assert_eq!((settlement.unlock_type, settlement.signer, settlement.since), (UnlockType::Htlc(0), htlcs[0].remote_htlc_pubkey_hash, Since::NONE));
+    let output = settlement.output.unwrap();
+    assert_eq!(output.script.pending_htlcs, vec![htlcs[1].clone()]);
+    assert_eq!(output.cell().lock_arg, CommitmentScript { pending_htlcs: vec![htlcs[1].clone()], ..state.script.clone() }.lock_arg());
+    assert_eq!(output.capacity, state.capacity);
+    assert_eq!(output.udt.unwrap().amount, 424242424242424242 - 1234567890);
+    assert_eq!(settlement.witness([0; 65]).preimage, Some([42; 32]));
+    let settlement = state.settle(&Unlock::HtlcPreimage { index: 1, preimage: [24; 32] });
+    assert_eq!(settlement.signer, htlcs[1].local_htlc_pubkey_hash);
+
+    // and the other way around once they expire
+    let settlement = state.settle(&Unlock::HtlcTimeout { index: 0 });
+    assert_eq!((settlement.signer, settlement.since), (htlcs[0].local_htlc_pubkey_hash, Since::absolute_timestamp(1711976400)));
+    assert_eq!(settlement.witness([0; 65]).preimage, None);
+    let settlement = state.settle(&Unlock::HtlcTimeout { index: 1 });
+    assert_eq!((settlement.signer, settlement.since), (htlcs[1].remote_htlc_pubkey_hash, Since::absolute_timestamp(1712062800)));
+
+    // a ckb cell pays out of its capacity
+    let (state, _) = settlement_state(&mut generator, false);
+    let output = state.settle(&Unlock::HtlcTimeout { index: 1 }).output.unwrap();
+    assert_eq!(output.capacity, Capacity::ckb(100) - Capacity::shannons(9876543210));
+    assert_eq!(output.udt, None);
+}
+
+#[test]
+#[should_panic(expected = "htlc 255 has no unlock type")]
+fn test_settlement_past_htlc_254() {
+    let mut generator = Generator::new();
+    let (mut state, _) = settlement_state(&mut generator, false);
+    state.script.pending_htlcs = pending_htlcs(&mut generator, 256);
+    state.settle(&Unlock::HtlcTimeout { index: 255 });
+}
+
+#[test]
+fn test_settlement_passes() {
+    let mut generator = Generator::new();
+    for udt in [true, false] {
+        let (state, keys) = settlement_state(&mut generator, udt);
+        for unlock in [
+            Unlock::Revoke,
+            Unlock::LocalDelay,
+            Unlock::HtlcPreimage { index: 0, preimage: [42; 32] },
+            Unlock::HtlcPreimage { index: 1, preimage: [24; 32] },
+            Unlock::HtlcTimeout { index: 0 },
+            Unlock::HtlcTimeout { index: 1 },
+        ] {
+            let mut ct = ContractUtil::new();
+            let settlement = state.settle(&unlock);
+            let tx = settlement_tx(&mut ct, &state, &settlement, settlement.output.as_ref(), &keys);
+            ct.context.should_be_passed(&tx, MAX_CYCLES * 10).unwrap_or_else(|err| panic!("{:?} of a {} cell: {:?}", unlock, if udt { "udt" } else { "ckb" }, err));
         }
-    };
+    }
 }
 
 #[test]
-fn test_empty_witness_args_err() {
+fn test_settlement_broken_output() {
+    let mut generator = Generator::new();
+    let (state, keys) = settlement_state(&mut generator, true);
+    let settlement = state.settle(&Unlock::HtlcPreimage { index: 0, preimage: [42; 32] });
+    let expected = settlement.output.clone().unwrap();
+
+    // args of the script before the settlement
+    let mut stale_args = expected.clone();
+    stale_args.script = state.script.clone();
+    let mut other_type = expected.clone();
+    other_type.udt.as_mut().unwrap().type_arg = [40; 32];
+    let mut less_capacity = expected.clone();
+    less_capacity.capacity = less_capacity.capacity - Capacity::shannons(1);
+    let mut more_amount = expected.clone();
+    more_amount.udt.as_mut().unwrap().amount += 1;
+
+    for (output, error) in [
+        (stale_args, CommitmentLockError::OutputLockError),
+        (other_type, CommitmentLockError::OutputTypeError),
+        (less_capacity, CommitmentLockError::OutputCapacityError),
+        (more_amount, CommitmentLockError::OutputUdtAmountError),
+    ] {
+        let mut ct = ContractUtil::new();
+        let tx = settlement_tx(&mut ct, &state, &settlement, Some(&output), &keys);
+        ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, error);
+    }
+
+    // a ckb cell must pay exactly the htlc amount out of its capacity
+    let (state, keys) = settlement_state(&mut generator, false);
+    let settlement = state.settle(&Unlock::HtlcTimeout { index: 0 });
+    let mut more_capacity = settlement.output.clone().unwrap();
+    more_capacity.capacity = more_capacity.capacity + Capacity::shannons(1);
     let mut ct = ContractUtil::new();
-    let commitment_contract = ct.deploy_contract("commitment-lock");
-    let auth_contract = ct.deploy_contract("auth");
+    let tx = settlement_tx(&mut ct, &state, &settlement, Some(&more_capacity), &keys);
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::OutputCapacityError);
+}
 
-    // prepare script
+#[test]
+fn test_settlement_before_expiry() {
     let mut generator = Generator::new();
-    // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
-    let local_delay_epoch_key = generator.gen_keypair();
-    let revocation_key = generator.gen_keypair();
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-    ].concat();
+    let (state, keys) = settlement_state(&mut generator, false);
+    for (unlock, early) in [
+        (Unlock::LocalDelay, Since::relative_epoch(10, 0, 2)),
+        (Unlock::HtlcTimeout { index: 0 }, Since::absolute_timestamp(1711976400 - 1)),
+    ] {
+        let mut settlement = state.settle(&unlock);
+        settlement.since = early;
+        let mut ct = ContractUtil::new();
+        let tx = settlement_tx(&mut ct, &state, &settlement, settlement.output.as_ref(), &keys);
+        ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::InvalidSince);
+    }
+}
 
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
+#[test]
+fn test_settlement_on_chain_clock() {
+    let mut generator = Generator::new();
+    let (state, keys) = settlement_state(&mut generator, false);
+    for unlock in [Unlock::LocalDelay, Unlock::HtlcTimeout { index: 0 }, Unlock::HtlcTimeout { index: 1 }] {
+        let mut ct = ContractUtil::new();
+        let settlement = state.settle(&unlock);
+        let tx = settlement_tx(&mut ct, &state, &settlement, settlement.output.as_ref(), &keys);
+        assert!(ct.check_since(&tx).is_err(), "{:?} is mature at genesis", unlock);
+
+        // the relative local delay counts from the block the commitment cell is in, htlc expiries are timestamps
+        match settlement.since.metric().unwrap() {
+            SinceMetric::Epoch { number, index, length } => ct.advance_epochs(number, index, length),
+            SinceMetric::Timestamp(expiry) => ct.advance_seconds(expiry - ct.clock.timestamp()),
+            metric => panic!("unexpected since {:?}", metric),
+        };
+        ct.check_since(&tx).unwrap();
+        ct.context.should_be_passed(&tx, 100000000).unwrap();
+    }
+}
 
-    let mut commitment_cell = CommitmentCellNoHtlcAndPreImage::default();
-    commitment_cell.lock_arg = <[u8; 20]>::try_from(args).unwrap();
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
+#[test]
+fn test_htlc_type_bits() {
+    assert_eq!(u8::from(HtlcType::OFFERED), 0b00);
+    assert_eq!(u8::from(HtlcType::RECEIVED), 0b01);
+    assert_eq!(u8::from(HtlcType::OFFERED.with_hash_algorithm(HashAlgorithm::Sha256)), 0b10);
+    assert_eq!(u8::from(HtlcType::RECEIVED.with_hash_algorithm(HashAlgorithm::Sha256)), 0b11);
+    for htlc_type in 0..=u8::MAX {
+        assert_eq!(u8::from(HtlcType::from(htlc_type)), htlc_type);
+    }
+    let reserved = HtlcType::from(0b11111101);
+    assert_eq!((reserved.direction, reserved.hash_algorithm, reserved.reserved), (HtlcDirection::Received, HashAlgorithm::CkbHash, 0b11111100));
 
-    // sign with revocation key
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-
-    let signature = revocation_key
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-    commitment_cell.witness = Some(CommitmentWitnessNoHtlcAndPreImage {
-        empty_witness_args: [1; 16],
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        unlock_type: 255,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-    });
-    let tx = ct.replace_output(tx, ct.alway_contract.clone(), None, &commitment_cell, 500, 0);
+    assert_eq!(HashAlgorithm::CkbHash.payment_hash([42u8; 32]), blake160([42u8; 32]));
+    assert_ne!(HashAlgorithm::Sha256.payment_hash([42u8; 32]), blake160([42u8; 32]));
+}
 
-    println!("tx: {:?}", tx);
+#[test]
+fn test_unlock_type_bytes() {
+    assert_eq!(u8::try_from(UnlockType::Revocation), Ok(0xFF));
+    assert_eq!(u8::try_from(UnlockType::LocalDelay), Ok(0xFF));
+    assert_eq!(u8::try_from(UnlockType::Htlc(0)), Ok(0));
+    assert_eq!(u8::try_from(UnlockType::Htlc(3)), Ok(3));
+    assert_eq!(u8::try_from(UnlockType::Raw(0xFF)), Ok(0xFF));
+    assert!(u8::try_from(UnlockType::Htlc(0xFF)).is_err());
+    assert_eq!(UnlockType::from(0xFF), UnlockType::Revocation);
+    assert_eq!(UnlockType::from(3), UnlockType::Htlc(3));
+
+    // an htlc index with no byte is an encoding error of the witness
+    let script = CommitmentScript { local_delay_epoch: 0, local_delay_pubkey_hash: [1; 20], revocation_pubkey_hash: [1; 20], pending_htlcs: Vec::new() };
+    let cell = CommitmentCell::new(script.lock_arg(), None, 0, Some(script.witness(UnlockType::Htlc(0xFF), [1; 65], None)));
+    let err = cell.try_get_witness().err().unwrap();
+    assert_eq!((err.field, err.op), (CellField::Witness, CellOp::Encode));
+}
 
-    // run
-    let cycles = match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            assert!(err.to_string().contains("code 11"))
+#[test]
+fn test_payment_hash_algorithm() {
+    let preimage = [42u8; 32];
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
+    let expiry = Since::absolute_timestamp(1711976400);
+    for direction in [HtlcDirection::Offered, HtlcDirection::Received] {
+        for hash_algorithm in [HashAlgorithm::CkbHash, HashAlgorithm::Sha256] {
+            // the payment hash is computed with either algorithm, the htlc type tells the contract which one to check
+            for payment_hash_algorithm in [HashAlgorithm::CkbHash, HashAlgorithm::Sha256] {
+                let mut generator = Generator::new();
+                let keys: Vec<(Privkey, Pubkey)> = (0..4).map(|_| generator.gen_keypair()).collect();
+                let htlc_type = HtlcType::new(direction, hash_algorithm);
+                let script = CommitmentScript::new(local_delay_epoch.as_u64(), &keys[0].1, &keys[1].1)
+                    .with_htlc(PendingHtlc::new(htlc_type, 1234567890, payment_hash_algorithm.payment_hash(preimage), &keys[2].1, &keys[3].1, expiry.as_u64()));
+                let state = CommitmentState::udt(script, Capacity::shannons(1000), [42; 32], 424242424242424242);
+                let settlement = state.settle(&Unlock::HtlcPreimage { index: 0, preimage });
+
+                let mut ct = ContractUtil::new();
+                let tx = settlement_tx(&mut ct, &state, &settlement, settlement.output.as_ref(), &keys);
+                if payment_hash_algorithm == hash_algorithm {
+                    ct.context.should_be_passed(&tx, 100000000).unwrap();
+                } else {
+                    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::PreimageError);
+                }
+            }
         }
-    };
+    }
 }
 
 #[test]
-fn test_witness_hash_err() {
+fn test_multiple_inputs_err() {
     let mut ct = ContractUtil::new();
     let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-    let mut commitment_cell = CommitmentCellNoHtlcAndPreImage::default();
-    commitment_cell.witness = Some(CommitmentWitnessNoHtlcAndPreImage {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: 0,
-        local_delay_pubkey_hash: [1; 20],
-        revocation_pubkey_hash: [1; 20],
-        unlock_type: 0,
-        signature: [1; 65],
-    });
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
+    let mut commitment_cell = CommitmentCell::default();
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
+
     println!("tx: {:?}", tx);
 
     // run
-    match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 12"))
-        }
-    };
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::MultipleInputs);
 }
 
-
 #[test]
-fn test_output_lock_err() {
-    // deploy contract
+fn test_invalid_since_err() {
+    // InvalidSince
     let mut ct = ContractUtil::new();
-    let commitment_lock_contract = ct.deploy_contract("commitment-lock");
+    let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-    let udt_contract = ct.deploy_contract("simple_udt");
-
 
     // prepare script
     let mut generator = Generator::new();
     // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
-    let remote_htlc_key1 = generator.gen_keypair();
-    let remote_htlc_key2 = generator.gen_keypair();
-    let local_htlc_key1 = generator.gen_keypair();
-    let local_htlc_key2 = generator.gen_keypair();
-    let preimage1 = [42u8; 32];
-    let preimage2 = [24u8; 32];
-    let payment_amount1 = 1234567890u128;
-    let payment_amount2 = 9876543210u128;
-    // timeout after 2024-04-01 01:00:00
-    let expiry1 = Since::from_timestamp(1711976400, true).unwrap();
-    // timeout after 2024-04-02 01:00:00
-    let expiry2 = Since::from_timestamp(1712062800, true).unwrap();
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000000].to_vec(),
-        payment_amount1.to_le_bytes().to_vec(),
-        blake2b_256(preimage1)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec(),
-        expiry1.as_u64().to_le_bytes().to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-    let total_sudt_amount = 424242424242424242u128;
-
-    let mut cc = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        type_arg: Some([42; 32]),
-        data: total_sudt_amount,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc, 1000);
 
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1);
 
-    let new_witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-    let args = blake2b_256(&new_witness_script)[0..20].to_vec();
+    let args = script.lock_arg();
 
-    let mut cc1 = CommitmentHTCL2WithPriImageAndUDTCell {
-        // lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        // output lock err
-        lock_arg: [0; 20],
+    let mut commitment_cell = CommitmentCell::default();
+    commitment_cell.lock_arg = args;
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input_with_since(&commitment_contract, None, &commitment_cell, Since::relative_epoch(10, 0, 2), Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
 
-        type_arg: cc.type_arg,
-        data: total_sudt_amount - payment_amount1,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    let tx = ct.context.complete_tx(tx);
-
-    // sign with remote_htlc_pubkey
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-    let signature = remote_htlc_key1
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-
-    cc1.witness = Some(CommitmentPendinghtlc2WithPriImageWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        pending_htlc1: PendingHtlc {
-            htlc_type: 0b00000000,
-            payment_amount: payment_amount1,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage1)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry1.as_u64(),
-        },
-        pending_htlc2: PendingHtlc {
-            htlc_type: 0b00000001,
-            payment_amount: payment_amount2,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage2)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry2.as_u64(),
-        },
-        unlock_type: 0x0,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-        preimage: preimage1,
-    });
-    let tx = ct.replace_output(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000, 0);
+    // sign with revocation key
+    let signature = sign(&revocation_key.0, &tx);
 
+    commitment_cell.witness = Some(script.witness(UnlockType::LocalDelay, signature, None));
+    let tx = ct.set_witness(tx, 0, &commitment_cell);
 
     println!("tx: {:?}", tx);
 
     // run
-    let err = ct.context
-        .verify_tx(&tx, 100000000)
-        .expect_err("OutputLockError");
-
-    println!("err: {}", err);
-    assert!(err.to_string().contains("code 14"))
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::InvalidSince);
 }
 
-
 #[test]
-fn test_output_type_err() {
-    // deploy contract
+fn test_invalid_unlock_type_err() {
     let mut ct = ContractUtil::new();
-    let commitment_lock_contract = ct.deploy_contract("commitment-lock");
+    let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-    let udt_contract = ct.deploy_contract("simple_udt");
-
 
     // prepare script
     let mut generator = Generator::new();
     // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
-    let remote_htlc_key1 = generator.gen_keypair();
-    let remote_htlc_key2 = generator.gen_keypair();
-    let local_htlc_key1 = generator.gen_keypair();
-    let local_htlc_key2 = generator.gen_keypair();
-    let preimage1 = [42u8; 32];
-    let preimage2 = [24u8; 32];
-    let payment_amount1 = 1234567890u128;
-    let payment_amount2 = 9876543210u128;
-    // timeout after 2024-04-01 01:00:00
-    let expiry1 = Since::from_timestamp(1711976400, true).unwrap();
-    // timeout after 2024-04-02 01:00:00
-    let expiry2 = Since::from_timestamp(1712062800, true).unwrap();
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000000].to_vec(),
-        payment_amount1.to_le_bytes().to_vec(),
-        blake2b_256(preimage1)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec(),
-        expiry1.as_u64().to_le_bytes().to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
 
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-    let total_sudt_amount = 424242424242424242u128;
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1);
 
-    let mut cc = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        type_arg: Some([42; 32]),
-        data: total_sudt_amount,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc, 1000);
+    let args = script.lock_arg();
 
+    let mut commitment_cell = CommitmentCell::default();
+    commitment_cell.lock_arg = args;
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
 
-    let new_witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-    let args = blake2b_256(&new_witness_script)[0..20].to_vec();
-
-    let mut cc1 = CommitmentHTCL2WithPriImageAndUDTCell {
-        // lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        // output lock err
-        lock_arg: [0; 20],
-        // output type err
-        // type_arg:cc.type_arg,
-        type_arg: Some([40; 32]),
-        data: total_sudt_amount - payment_amount1,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    let tx = ct.context.complete_tx(tx);
-
-    // sign with remote_htlc_pubkey
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-    let signature = remote_htlc_key1
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-
-    cc1.witness = Some(CommitmentPendinghtlc2WithPriImageWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        pending_htlc1: PendingHtlc {
-            htlc_type: 0b00000000,
-            payment_amount: payment_amount1,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage1)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry1.as_u64(),
-        },
-        pending_htlc2: PendingHtlc {
-            htlc_type: 0b00000001,
-            payment_amount: payment_amount2,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage2)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry2.as_u64(),
-        },
-        unlock_type: 0x0,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-        preimage: preimage1,
-    });
-    let tx = ct.replace_output(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000, 0);
+    // sign with revocation key
+    let signature = sign(&revocation_key.0, &tx);
 
+    commitment_cell.witness = Some(script.witness(UnlockType::Raw(254), signature, None));
+    let tx = ct.set_witness(tx, 0, &commitment_cell);
 
     println!("tx: {:?}", tx);
 
     // run
-    let err = ct.context
-        .verify_tx(&tx, 100000000)
-        .expect_err("OutputLockError");
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::InvalidUnlockType);
+}
 
-    println!("err: {}", err);
-    assert!(err.to_string().contains("code 14"))
+#[test]
+fn test_invalid_htlc_type_err() {
+    // the commitment-lock in build/release reads bits 0 and 1 of the htlc type and never returns
+    // InvalidHtlcType, an htlc with the reserved bits set settles by its direction and hash algorithm
+    let mut generator = Generator::new();
+    for udt in [true, false] {
+        let (mut state, keys) = settlement_state(&mut generator, udt);
+        state.script.pending_htlcs[0] = state.script.pending_htlcs[0].clone().with_raw_htlc_type(0b11111100);
+        state.script.pending_htlcs[1] = state.script.pending_htlcs[1].clone().with_raw_htlc_type(0b11111101);
+        assert_eq!(state.script.witness_script()[8 + 20 + 20], 0b11111100);
+        for unlock in [
+            Unlock::HtlcPreimage { index: 0, preimage: [42; 32] },
+            Unlock::HtlcPreimage { index: 1, preimage: [24; 32] },
+            Unlock::HtlcTimeout { index: 0 },
+            Unlock::HtlcTimeout { index: 1 },
+        ] {
+            let mut ct = ContractUtil::new();
+            let settlement = state.settle(&unlock);
+            let tx = settlement_tx(&mut ct, &state, &settlement, settlement.output.as_ref(), &keys);
+            ct.context.should_be_passed(&tx, MAX_CYCLES * 10).unwrap_or_else(|err| panic!("{:?} with reserved htlc type bits: {:?}", unlock, err));
+        }
+    }
 }
 
 #[test]
-fn test_output_udt_amount_err() {
+fn test_args_len_error() {
     let mut ct = ContractUtil::new();
-    let commitment_lock_contract = ct.deploy_contract("commitment-lock");
+    let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-    let udt_contract = ct.deploy_contract("simple_udt");
-
-
-    // prepare script
-    let mut generator = Generator::new();
-    // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
-    let local_delay_epoch_key = generator.gen_keypair();
-    let revocation_key = generator.gen_keypair();
-    let remote_htlc_key1 = generator.gen_keypair();
-    let remote_htlc_key2 = generator.gen_keypair();
-    let local_htlc_key1 = generator.gen_keypair();
-    let local_htlc_key2 = generator.gen_keypair();
-    let preimage1 = [42u8; 32];
-    let preimage2 = [24u8; 32];
-    let payment_amount1 = 1234567890u128;
-    let payment_amount2 = 9876543210u128;
-    // timeout after 2024-04-01 01:00:00
-    let expiry1 = Since::from_timestamp(1711976400, true).unwrap();
-    // timeout after 2024-04-02 01:00:00
-    let expiry2 = Since::from_timestamp(1712062800, true).unwrap();
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000000].to_vec(),
-        payment_amount1.to_le_bytes().to_vec(),
-        blake2b_256(preimage1)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec(),
-        expiry1.as_u64().to_le_bytes().to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-    let total_sudt_amount = 424242424242424242u128;
-
-    let mut cc = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        type_arg: Some([42; 32]),
-        data: total_sudt_amount,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc, 1000);
-
-
-    let new_witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-    let args = blake2b_256(&new_witness_script)[0..20].to_vec();
-
-    let mut cc1 = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-
-        type_arg: cc.type_arg,
-        // udtAmountErr
-        // data: total_sudt_amount - payment_amount1,
-        data: total_sudt_amount - payment_amount1 + 1,
-
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    let tx = ct.context.complete_tx(tx);
-
-    // sign with remote_htlc_pubkey
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-    let signature = remote_htlc_key1
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-
-    cc1.witness = Some(CommitmentPendinghtlc2WithPriImageWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        pending_htlc1: PendingHtlc {
-            htlc_type: 0b00000000,
-            payment_amount: payment_amount1,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage1)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry1.as_u64(),
-        },
-        pending_htlc2: PendingHtlc {
-            htlc_type: 0b00000001,
-            payment_amount: payment_amount2,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage2)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry2.as_u64(),
-        },
-        unlock_type: 0x0,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-        preimage: preimage1,
-    });
-    let tx = ct.replace_output(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000, 0);
-
+    // 22 bytes lock args
+    let commitment_cell = MalformedCell::from_cell(&CommitmentCell::default()).extend(CellField::LockArg, &[0; 2]);
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
 
     println!("tx: {:?}", tx);
 
     // run
-    let err = ct.context
-        .verify_tx(&tx, 100000000)
-        .expect_err("outputUdtAmountErr");
-
-    println!("err: {}", err);
-    assert!(err.to_string().contains("code 16"))
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::ArgsLenError);
 }
 
 #[test]
-fn test_preimage_received_err() {
+fn test_witness_len_err_too_min() {
     let mut ct = ContractUtil::new();
     let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-
-    // prepare script
-    let mut generator = Generator::new();
-    // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
-    let local_delay_epoch_key = generator.gen_keypair();
-    let revocation_key = generator.gen_keypair();
-    let remote_htlc_key1 = generator.gen_keypair();
-    let remote_htlc_key2 = generator.gen_keypair();
-    let local_htlc_key1 = generator.gen_keypair();
-    let local_htlc_key2 = generator.gen_keypair();
-    let preimage1 = [42u8; 32];
-    let preimage2 = [24u8; 32];
-    let payment_amount1 = 5 * BYTE_SHANNONS as u128;
-    let payment_amount2 = 8 * BYTE_SHANNONS as u128;
-    // timeout after 2024-04-01 01:00:00
-    let expiry1 = Since::from_timestamp(1711976400, true).unwrap();
-    // timeout after 2024-04-02 01:00:00
-    let expiry2 = Since::from_timestamp(1712062800, true).unwrap();
-
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000000].to_vec(),
-        payment_amount1.to_le_bytes().to_vec(),
-        blake2b_256(preimage1)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec(),
-        expiry1.as_u64().to_le_bytes().to_vec(),
-        [0b00000011].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        Sha256::digest(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-
-    let mut cell = CommitmentHTCL2Cell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        type_arg: None,
-        data: 0,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, commitment_contract.clone(), None, &cell, 1000);
-
-    let tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &cell, 500);
-    let tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &cell, 500);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    let tx = ct.context.complete_tx(tx);
-
-    // sign with revocation key
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-
-    let signature = revocation_key
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-    cell.witness = Some(CommitmentPendinghtlc2Witness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        pending_htlc1: PendingHtlc {
-            htlc_type: 0,
-            payment_amount: payment_amount1,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage1)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry1.as_u64(),
-        },
-        pending_htlc2: PendingHtlc {
-            htlc_type: 0b00000011,
-            payment_amount: payment_amount2,
-            payment_hash: <[u8; 20]>::try_from(Sha256::digest(preimage2)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry2.as_u64(),
-        },
-        unlock_type: 0x1,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-    });
-    let tx = ct.replace_output(tx, ct.alway_contract.clone(), None, &cell, 500, 0);
+    let script = CommitmentScript { local_delay_epoch: 0, local_delay_pubkey_hash: [1; 20], revocation_pubkey_hash: [1; 20], pending_htlcs: Vec::new() };
+    let mut commitment_cell = CommitmentCell::default();
+    commitment_cell.witness = Some(script.witness(UnlockType::Htlc(0), [1; 65], None));
+    // without unlock_type and signature
+    let commitment_cell = MalformedCell::from_cell(&commitment_cell).truncate(CellField::Witness, CommitmentWitness::SUFFIX_LEN);
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
+    println!("tx: {:?}", tx);
 
     // run
-    let err = ct.context
-        .verify_tx(&tx, 100000000)
-        .expect_err("Error::PreimageError");
-    assert!(err.to_string().contains("code 17"))
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::WitnessLenError);
 }
 
 #[test]
-fn test_preimage_offer_err() {
+fn test_witness_len_err_too_big() {
     let mut ct = ContractUtil::new();
     let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-
-    // prepare script
-    let mut generator = Generator::new();
-    // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
-    let local_delay_epoch_key = generator.gen_keypair();
-    let revocation_key = generator.gen_keypair();
-    let remote_htlc_key1 = generator.gen_keypair();
-    let remote_htlc_key2 = generator.gen_keypair();
-    let local_htlc_key1 = generator.gen_keypair();
-    let local_htlc_key2 = generator.gen_keypair();
-    let preimage1 = [42u8; 32];
-    let preimage2 = [24u8; 32];
-    let payment_amount1 = 5 * BYTE_SHANNONS as u128;
-    let payment_amount2 = 8 * BYTE_SHANNONS as u128;
-    // timeout after 2024-04-01 01:00:00
-    let expiry1 = Since::from_timestamp(1711976400, true).unwrap();
-    // timeout after 2024-04-02 01:00:00
-    let expiry2 = Since::from_timestamp(1712062800, true).unwrap();
-
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000000].to_vec(),
-        payment_amount1.to_le_bytes().to_vec(),
-        blake2b_256(preimage1)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec(),
-        expiry1.as_u64().to_le_bytes().to_vec(),
-        [0b00000011].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        Sha256::digest(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-
-    let mut cell = CommitmentHTCL2Cell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        type_arg: None,
-        data: 0,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, commitment_contract.clone(), None, &cell, 1000);
-
-    let tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &cell, 500);
-    let tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &cell, 500);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    let tx = ct.context.complete_tx(tx);
-
-    // sign with revocation key
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-
-    let signature = revocation_key
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-    cell.witness = Some(CommitmentPendinghtlc2Witness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        pending_htlc1: PendingHtlc {
-            htlc_type: 0,
-            payment_amount: payment_amount1,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage1)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry1.as_u64(),
-        },
-        pending_htlc2: PendingHtlc {
-            htlc_type: 0b00000011,
-            payment_amount: payment_amount2,
-            payment_hash: <[u8; 20]>::try_from(Sha256::digest(preimage2)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry2.as_u64(),
-        },
-        unlock_type: 0x0,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-    });
-    let tx = ct.replace_output(tx, ct.alway_contract.clone(), None, &cell, 500, 0);
+    let script = CommitmentScript { local_delay_epoch: 0, local_delay_pubkey_hash: [1; 20], revocation_pubkey_hash: [1; 20], pending_htlcs: Vec::new() };
+    let mut commitment_cell = CommitmentCell::default();
+    commitment_cell.witness = Some(script.witness(UnlockType::Htlc(0), [1; 65], None));
+    let commitment_cell = MalformedCell::from_cell(&commitment_cell).extend(CellField::Witness, &[1; 5]);
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
+    println!("tx: {:?}", tx);
 
     // run
-    let err = ct.context
-        .verify_tx(&tx, 100000000)
-        .expect_err("Error::PreimageError");
-    println!("err:{:?}", err);
-    assert!(err.to_string().contains("code 17"))
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::WitnessLenError);
 }
 
 #[test]
-fn test_udt_output_capacity_err() {
+fn test_empty_witness_args_err() {
     let mut ct = ContractUtil::new();
-    let commitment_lock_contract = ct.deploy_contract("commitment-lock");
+    let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-    let udt_contract = ct.deploy_contract("simple_udt");
-
 
     // prepare script
     let mut generator = Generator::new();
     // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
-    let remote_htlc_key1 = generator.gen_keypair();
-    let remote_htlc_key2 = generator.gen_keypair();
-    let local_htlc_key1 = generator.gen_keypair();
-    let local_htlc_key2 = generator.gen_keypair();
-    let preimage1 = [42u8; 32];
-    let preimage2 = [24u8; 32];
-    let payment_amount1 = 1234567890u128;
-    let payment_amount2 = 9876543210u128;
-    // timeout after 2024-04-01 01:00:00
-    let expiry1 = Since::from_timestamp(1711976400, true).unwrap();
-    // timeout after 2024-04-02 01:00:00
-    let expiry2 = Since::from_timestamp(1712062800, true).unwrap();
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000000].to_vec(),
-        payment_amount1.to_le_bytes().to_vec(),
-        blake2b_256(preimage1)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec(),
-        expiry1.as_u64().to_le_bytes().to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-    let total_sudt_amount = 424242424242424242u128;
 
-    let mut cc = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        type_arg: Some([42; 32]),
-        data: total_sudt_amount,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc, 1000);
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1);
 
+    let args = script.lock_arg();
 
-    let new_witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-    let args = blake2b_256(&new_witness_script)[0..20].to_vec();
+    let mut commitment_cell = CommitmentCell::default();
+    commitment_cell.lock_arg = args;
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
 
-    let mut cc1 = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
+    // sign with revocation key
+    let signature = sign(&revocation_key.0, &tx);
 
-        type_arg: cc.type_arg,
-        data: total_sudt_amount - payment_amount1,
+    commitment_cell.witness = Some(script.witness(UnlockType::Revocation, signature, None));
+    // the full size of the WitnessArgs is no longer 16
+    let commitment_cell = MalformedCell::from_cell(&commitment_cell).flip_byte(CellField::Witness, 0);
+    let tx = ct.set_witness(tx, 0, &commitment_cell);
 
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    // err: OutputCapacityError
-    // let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 999);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    let tx = ct.context.complete_tx(tx);
-
-    // sign with remote_htlc_pubkey
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-    let signature = remote_htlc_key1
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-
-    cc1.witness = Some(CommitmentPendinghtlc2WithPriImageWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        pending_htlc1: PendingHtlc {
-            htlc_type: 0b00000000,
-            payment_amount: payment_amount1,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage1)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry1.as_u64(),
-        },
-        pending_htlc2: PendingHtlc {
-            htlc_type: 0b00000001,
-            payment_amount: payment_amount2,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage2)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry2.as_u64(),
-        },
-        unlock_type: 0x0,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-        preimage: preimage1,
-    });
-    // err: OutputCapacityError
-    // let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.replace_output(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 999, 0);
+    println!("tx: {:?}", tx);
 
+    // run
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::EmptyWitnessArgsError);
+}
 
+#[test]
+fn test_witness_hash_err() {
+    let mut ct = ContractUtil::new();
+    let commitment_contract = ct.deploy_contract("commitment-lock");
+    let auth_contract = ct.deploy_contract("auth");
+    let script = CommitmentScript { local_delay_epoch: 0, local_delay_pubkey_hash: [1; 20], revocation_pubkey_hash: [1; 20], pending_htlcs: Vec::new() };
+    let mut commitment_cell = CommitmentCell::default();
+    commitment_cell.witness = Some(script.witness(UnlockType::Htlc(0), [1; 65], None));
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
     println!("tx: {:?}", tx);
 
     // run
-    let err = ct.context
-        .verify_tx(&tx, 100000000)
-        .expect_err("OutputCapacityError");
-
-    println!("err: {}", err);
-    assert!(err.to_string().contains("code 13"))
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::WitnessHashError);
 }
 
-
 #[test]
-fn test_output_capacity_err() {
+fn test_preimage_received_err() {
     let mut ct = ContractUtil::new();
-    let commitment_lock_contract = ct.deploy_contract("commitment-lock");
+    let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-    let udt_contract = ct.deploy_contract("simple_udt");
-
 
     // prepare script
     let mut generator = Generator::new();
     // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
     let remote_htlc_key1 = generator.gen_keypair();
@@ -1201,135 +724,48 @@ fn test_output_capacity_err() {
     let local_htlc_key2 = generator.gen_keypair();
     let preimage1 = [42u8; 32];
     let preimage2 = [24u8; 32];
-    let payment_amount1 = 2u128;
-    let payment_amount2 = 2u128;
+    let payment_amount1 = 5 * SHANNONS_PER_CKB as u128;
+    let payment_amount2 = 8 * SHANNONS_PER_CKB as u128;
     // timeout after 2024-04-01 01:00:00
-    let expiry1 = Since::from_timestamp(1711976400, true).unwrap();
+    let expiry1 = Since::absolute_timestamp(1711976400);
     // timeout after 2024-04-02 01:00:00
-    let expiry2 = Since::from_timestamp(1712062800, true).unwrap();
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000000].to_vec(),
-        payment_amount1.to_le_bytes().to_vec(),
-        blake2b_256(preimage1)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec(),
-        expiry1.as_u64().to_le_bytes().to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-    let total_sudt_amount = 424242424242424242u128;
-
-    let mut cc = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        type_arg: Some([42; 32]),
-        data: total_sudt_amount,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, commitment_lock_contract.clone(), None, &cc, 1000);
-
-
-    let new_witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-    let args = blake2b_256(&new_witness_script)[0..20].to_vec();
+    let expiry2 = Since::absolute_timestamp(1712062800);
 
-    let mut cc1 = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
 
-        type_arg: cc.type_arg,
-        data: total_sudt_amount - payment_amount1,
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1)
+        .with_htlc(PendingHtlc::new(HtlcType::OFFERED, payment_amount1, blake160(preimage1), &remote_htlc_key1.1, &local_htlc_key1.1, expiry1.as_u64()))
+        .with_htlc(PendingHtlc::new(HtlcType::RECEIVED.with_hash_algorithm(HashAlgorithm::Sha256), payment_amount2, HashAlgorithm::Sha256.payment_hash(preimage2), &remote_htlc_key2.1, &local_htlc_key2.1, expiry2.as_u64()));
+    let args = script.lock_arg();
 
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    // err: OutputCapacityError
-    // let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), None, &cc1, 999);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    let tx = ct.context.complete_tx(tx);
-
-    // sign with remote_htlc_pubkey
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-    let signature = remote_htlc_key1
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-
-    cc1.witness = Some(CommitmentPendinghtlc2WithPriImageWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        pending_htlc1: PendingHtlc {
-            htlc_type: 0b00000000,
-            payment_amount: payment_amount1,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage1)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry1.as_u64(),
-        },
-        pending_htlc2: PendingHtlc {
-            htlc_type: 0b00000001,
-            payment_amount: payment_amount2,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage2)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry2.as_u64(),
-        },
-        unlock_type: 0x0,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-        preimage: preimage1,
-    });
-    // err: OutputCapacityError
-    // let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.replace_output(tx, commitment_lock_contract.clone(), None, &cc1, 999, 0);
+    let mut cell = CommitmentCell::new(args, None, 0, None);
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &cell, Capacity::shannons(1000))
+        .output(&always_success, None, &cell, Capacity::shannons(500))
+        .output(&always_success, None, &cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
 
+    // sign with revocation key
+    let signature = sign(&revocation_key.0, &tx);
 
-    println!("tx: {:?}", tx);
+    cell.witness = Some(script.witness(UnlockType::Htlc(1), signature, None));
+    let tx = ct.set_witness(tx, 0, &cell);
 
     // run
-    let err = ct.context
-        .verify_tx(&tx, 100000000)
-        .expect_err("OutputCapacityError");
-
-    println!("err: {}", err);
-    assert!(err.to_string().contains("code 13"))
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::PreimageError);
 }
 
 #[test]
-fn test_output_capacity_overflow() {
+fn test_preimage_offer_err() {
     let mut ct = ContractUtil::new();
-    let commitment_lock_contract = ct.deploy_contract("commitment-lock");
+    let commitment_contract = ct.deploy_contract("commitment-lock");
     let auth_contract = ct.deploy_contract("auth");
-    let udt_contract = ct.deploy_contract("simple_udt");
-
 
     // prepare script
     let mut generator = Generator::new();
     // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
     let remote_htlc_key1 = generator.gen_keypair();
@@ -1338,122 +774,49 @@ fn test_output_capacity_overflow() {
     let local_htlc_key2 = generator.gen_keypair();
     let preimage1 = [42u8; 32];
     let preimage2 = [24u8; 32];
-    // 1000 - 20000 -> subtract with overflow
-    let payment_amount1 = 20000u128;
-    let payment_amount2 = 2u128;
+    let payment_amount1 = 5 * SHANNONS_PER_CKB as u128;
+    let payment_amount2 = 8 * SHANNONS_PER_CKB as u128;
     // timeout after 2024-04-01 01:00:00
-    let expiry1 = Since::from_timestamp(1711976400, true).unwrap();
+    let expiry1 = Since::absolute_timestamp(1711976400);
     // timeout after 2024-04-02 01:00:00
-    let expiry2 = Since::from_timestamp(1712062800, true).unwrap();
-
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000000].to_vec(),
-        payment_amount1.to_le_bytes().to_vec(),
-        blake2b_256(preimage1)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec(),
-        expiry1.as_u64().to_le_bytes().to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
+    let expiry2 = Since::absolute_timestamp(1712062800);
 
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
-    let total_sudt_amount = 424242424242424242u128;
 
-    let mut cc = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-        type_arg: Some([42; 32]),
-        data: total_sudt_amount,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, commitment_lock_contract.clone(), None, &cc, 1000);
-
-
-    let new_witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-        [0b00000001].to_vec(),
-        payment_amount2.to_le_bytes().to_vec(),
-        blake2b_256(preimage2)[0..20].to_vec(),
-        blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec(),
-        blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec(),
-        expiry2.as_u64().to_le_bytes().to_vec(),
-    ].concat();
-    let args = blake2b_256(&new_witness_script)[0..20].to_vec();
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1)
+        .with_htlc(PendingHtlc::new(HtlcType::OFFERED, payment_amount1, blake160(preimage1), &remote_htlc_key1.1, &local_htlc_key1.1, expiry1.as_u64()))
+        .with_htlc(PendingHtlc::new(HtlcType::RECEIVED.with_hash_algorithm(HashAlgorithm::Sha256), payment_amount2, HashAlgorithm::Sha256.payment_hash(preimage2), &remote_htlc_key2.1, &local_htlc_key2.1, expiry2.as_u64()));
+    let args = script.lock_arg();
 
-    let mut cc1 = CommitmentHTCL2WithPriImageAndUDTCell {
-        lock_arg: <[u8; 20]>::try_from(args).unwrap(),
-
-        type_arg: cc.type_arg,
-        data: total_sudt_amount - payment_amount1,
-
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
-    // err: OutputCapacityError
-    // let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), None, &cc1, 999);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-    let tx = ct.context.complete_tx(tx);
-
-    // sign with remote_htlc_pubkey
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-    let signature = remote_htlc_key1
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-
-    cc1.witness = Some(CommitmentPendinghtlc2WithPriImageWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        pending_htlc1: PendingHtlc {
-            htlc_type: 0b00000000,
-            payment_amount: payment_amount1,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage1)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key1.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry1.as_u64(),
-        },
-        pending_htlc2: PendingHtlc {
-            htlc_type: 0b00000001,
-            payment_amount: payment_amount2,
-            payment_hash: <[u8; 20]>::try_from(blake2b_256(preimage2)[0..20].to_vec()).unwrap(),
-            remote_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(remote_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            local_htlc_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_htlc_key2.1.serialize())[0..20].to_vec()).unwrap(),
-            htlc_expiry: expiry2.as_u64(),
-        },
-        unlock_type: 0x0,
-        signature: <[u8; 65]>::try_from(signature).unwrap(),
-        preimage: preimage1,
-    });
-    // err: OutputCapacityError
-    // let tx = ct.add_outpoint(tx, commitment_lock_contract.clone(), Some(udt_contract.clone()), &cc1, 1000);
-    let tx = ct.replace_output(tx, commitment_lock_contract.clone(), None, &cc1, 999, 0);
+    let mut cell = CommitmentCell::new(args, None, 0, None);
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &cell, Capacity::shannons(1000))
+        .output(&always_success, None, &cell, Capacity::shannons(500))
+        .output(&always_success, None, &cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
 
+    // sign with revocation key
+    let signature = sign(&revocation_key.0, &tx);
 
-    println!("tx: {:?}", tx);
+    cell.witness = Some(script.witness(UnlockType::Htlc(0), signature, None));
+    let tx = ct.set_witness(tx, 0, &cell);
 
     // run
-    let err = ct.context
-        .verify_tx(&tx, 100000000)
-        .expect_err("subtract with overflow");
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::PreimageError);
+}
 
-    println!("err: {}", err);
-    assert!(err.to_string().contains("code -1"))
+#[test]
+fn test_output_capacity_overflow() {
+    let mut generator = Generator::new();
+    let (state, keys) = settlement_state(&mut generator, false);
+    let settlement = state.settle(&Unlock::HtlcTimeout { index: 1 });
+    // the input holds less than the htlc pays out, the contract overflows subtracting it
+    let mut poor = state.clone();
+    poor.capacity = Capacity::shannons(9876543210 - 1);
+    let mut ct = ContractUtil::new();
+    let tx = settlement_tx(&mut ct, &poor, &settlement, settlement.output.as_ref(), &keys);
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::Panic);
 }
 
 #[test]
@@ -1465,57 +828,29 @@ fn test_auth_err() {
     // prepare script
     let mut generator = Generator::new();
     // 42 hours = 4.5 epochs
-    let local_delay_epoch = Since::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false);
+    let local_delay_epoch = Since::relative_epoch(10, 1, 2);
     let local_delay_epoch_key = generator.gen_keypair();
     let revocation_key = generator.gen_keypair();
 
-    let witness_script = [
-        local_delay_epoch.as_u64().to_le_bytes().to_vec(),
-        blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec(),
-        blake2b_256(revocation_key.1.serialize())[0..20].to_vec(),
-    ].concat();
+    let script = CommitmentScript::new(local_delay_epoch.as_u64(), &local_delay_epoch_key.1, &revocation_key.1);
 
-    let args = blake2b_256(&witness_script)[0..20].to_vec();
+    let args = script.lock_arg();
 
-    let mut commitment_cell = CommitmentCellNoHtlcAndPreImage::default();
-    commitment_cell.lock_arg = <[u8; 20]>::try_from(args).unwrap();
-    let mut tx = TransactionBuilder::default().build();
-    tx = ct.add_input(tx, commitment_contract.clone(), None, &commitment_cell, 1000);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), None, &commitment_cell, 500);
-    let tx = ct.context.complete_tx(tx);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
+    let mut commitment_cell = CommitmentCell::default();
+    commitment_cell.lock_arg = args;
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&commitment_contract, None, &commitment_cell, Capacity::shannons(1000))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .output(&always_success, None, &commitment_cell, Capacity::shannons(500))
+        .dep(&auth_contract)
+        .build();
 
-    // sign with revocation key
-    let message: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
-
-    let signature = revocation_key
-        .0
-        .sign_recoverable(&message.into())
-        .unwrap()
-        .serialize();
-
-    commitment_cell.witness = Some(CommitmentWitnessNoHtlcAndPreImage {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        local_delay_epoch: local_delay_epoch.as_u64(),
-        local_delay_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(local_delay_epoch_key.1.serialize())[0..20].to_vec()).unwrap(),
-        revocation_pubkey_hash: <[u8; 20]>::try_from(blake2b_256(revocation_key.1.serialize())[0..20].to_vec()).unwrap(),
-        unlock_type: 255,
-        signature: [1; 65],
-    });
-    let tx = ct.replace_output(tx, ct.alway_contract.clone(), None, &commitment_cell, 500, 0);
+    commitment_cell.witness = Some(script.witness(UnlockType::Revocation, [1; 65], None));
+    let tx = ct.set_witness(tx, 0, &commitment_cell);
 
     println!("tx: {:?}", tx);
 
     // run
-    match ct.context
-        .verify_tx(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 101"))
-        }
-    };
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, CommitmentLockError::AuthMismatched);
 }
\ No newline at end of file
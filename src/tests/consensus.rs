@@ -0,0 +1,68 @@
+use ckb_testtool::ckb_types::bytes::Bytes;
+use ckb_testtool::ckb_types::core::TransactionView;
+use ckb_testtool::ckb_types::prelude::Pack;
+use crate::capacity::Capacity;
+use crate::cells::demo::Demo;
+use crate::chain::consensus::{check_tx, ConsensusError};
+use crate::prelude::{ContextExt, MAX_CYCLES};
+use crate::ContractUtil;
+
+fn transfer(ct: &mut ContractUtil, input: Capacity, output: Capacity) -> TransactionView {
+    let always_success = ct.alway_contract.clone();
+    ct.tx()
+        .input(&always_success, None, &Demo::default(), input)
+        .output(&always_success, None, &Demo::default(), output)
+        .build()
+}
+
+#[test]
+fn test_strict_passes() {
+    let mut ct = ContractUtil::new();
+    let tx = transfer(&mut ct, Capacity::occupied_plus_ckb(100), Capacity::occupied_plus_ckb(60));
+    assert_eq!(check_tx(&ct.context, &tx), Ok(()));
+    ct.context.strict().should_be_passed(&tx, MAX_CYCLES).unwrap();
+}
+
+// scripts pass on capacities no node would accept
+#[test]
+fn test_strict_occupied_capacity() {
+    let mut ct = ContractUtil::new();
+    let tx = transfer(&mut ct, Capacity::shannons(1000), Capacity::shannons(500));
+    ct.context.should_be_passed(&tx, MAX_CYCLES).unwrap();
+
+    assert!(matches!(check_tx(&ct.context, &tx), Err(ConsensusError::InsufficientCellCapacity { index: 0, capacity: 500, .. })));
+    let err = ct.context.strict().should_be_failed(&tx, MAX_CYCLES).unwrap_err();
+    assert!(err.to_string().contains("consensus: output 0 holds 500 shannons"), "{}", err);
+}
+
+#[test]
+fn test_strict_outputs_exceed_inputs() {
+    let mut ct = ContractUtil::new();
+    let tx = transfer(&mut ct, Capacity::occupied_plus_ckb(100), Capacity::occupied_plus_ckb(101));
+    ct.context.should_be_passed(&tx, MAX_CYCLES).unwrap();
+    assert!(matches!(check_tx(&ct.context, &tx), Err(ConsensusError::OutputsExceedInputs { .. })));
+    ct.context.strict().should_be_failed(&tx, MAX_CYCLES).unwrap_err();
+}
+
+#[test]
+fn test_strict_tx_layout() {
+    let mut ct = ContractUtil::new();
+    let tx = transfer(&mut ct, Capacity::occupied_plus_ckb(100), Capacity::occupied_plus_ckb(60));
+
+    let extra_data = tx.as_advanced_builder().output_data(Bytes::new().pack()).build();
+    assert_eq!(check_tx(&ct.context, &extra_data), Err(ConsensusError::OutputsDataLengthMismatch { outputs: 1, outputs_data: 2 }));
+
+    let cell_dep = tx.cell_deps().get(0).unwrap();
+    let duplicate_dep = tx.as_advanced_builder().cell_dep(cell_dep).build();
+    assert_eq!(check_tx(&ct.context, &duplicate_dep), Err(ConsensusError::DuplicateCellDep { index: tx.cell_deps().len() }));
+
+    let input = tx.inputs().get(0).unwrap();
+    let duplicate_input = tx.as_advanced_builder().input(input).build();
+    assert_eq!(check_tx(&ct.context, &duplicate_input), Err(ConsensusError::DuplicateInput { index: 1 }));
+
+    let no_outputs = tx.as_advanced_builder().set_outputs(Vec::new()).set_outputs_data(Vec::new()).build();
+    assert_eq!(check_tx(&ct.context, &no_outputs), Err(ConsensusError::EmptyOutputs));
+
+    let version = tx.as_advanced_builder().version(1u32.pack()).build();
+    assert_eq!(check_tx(&ct.context, &version), Err(ConsensusError::Version(1)));
+}
@@ -1,6 +1,8 @@
 use ckb_testtool::ckb_types::core::TransactionBuilder;
-use ckb_testtool::ckb_types::prelude::Builder;
+use ckb_testtool::ckb_types::prelude::{Builder, Entity, Unpack};
+use crate::capacity::Capacity;
 use crate::cells::xudt_data::{XUDTData, XUDTDataCell};
+use crate::cell_message::cell::Cell;
 use crate::ContractUtil;
 use crate::prelude::ContextExt;
 
@@ -18,21 +20,133 @@ fn test_contract_opt() {
     let type_contract = ct.deploy_contract("XUDT");
     let mut tx = TransactionBuilder::default().build();
 
-    tx = ct.add_input(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &input_token_cell, 100);
-    tx = ct.add_input(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &input_token2_cell, 100);
+    tx = ct.add_input(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &input_token_cell, Capacity::shannons(100));
+    tx = ct.add_input(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &input_token2_cell, Capacity::shannons(100));
 
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &output_token1_cell, 100);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &output_token2_cell, 100);
+    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &output_token1_cell, Capacity::shannons(100));
+    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &output_token2_cell, Capacity::shannons(100));
 
-    tx = ct.set_output(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &set_0_output_cell, 100, 0);
+    tx = ct.set_output(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &set_0_output_cell, Capacity::shannons(100), 0);
 
-    tx = ct.replace_output(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &replace_0_output_cell, 100, 1);
+    tx = ct.replace_output(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &replace_0_output_cell, Capacity::shannons(100), 1);
 
     tx = ct.context.complete_tx(tx);
     let ret1 = ct.context.should_be_passed(&tx, 1000000);
 
     println!("ret:{:?}", ret1);
-    let output1: XUDTDataCell = ct.get_cell_by_index(tx, 0);
+    let output1: XUDTDataCell = ct.get_cell_by_index(tx.clone(), 0).unwrap();
     println!("output1:{:?}", output1.data);
-    assert_eq!(output1, set_0_output_cell);
-}
\ No newline at end of file
+    assert_eq!(output1.type_arg, set_0_output_cell.type_arg);
+    assert_eq!(output1.data, set_0_output_cell.data);
+    // witnesses follow the inputs, not the outputs
+    assert_eq!(output1.witness, None);
+    assert_eq!(tx.witnesses().len(), 2);
+}
+
+#[test]
+fn test_tx_scenario_matches_manual_building() {
+    let input_token_cell = XUDTDataCell::new([1; 32], XUDTData { amount: 2000 });
+    let output_token_cell = XUDTDataCell::new([1; 32], XUDTData { amount: 2000 });
+    let inserted_cell = XUDTDataCell::new([1; 32], XUDTData { amount: 0 });
+
+    let mut ct = ContractUtil::new();
+    let type_contract = ct.deploy_contract("XUDT");
+    let always_success = ct.alway_contract.clone();
+
+    let scenario_tx = ct.tx()
+        .input(&always_success, Some(&type_contract), &input_token_cell, Capacity::shannons(100))
+        .output(&always_success, Some(&type_contract), &output_token_cell, Capacity::shannons(100))
+        .set_output(&always_success, Some(&type_contract), &inserted_cell, Capacity::shannons(100), 0)
+        .dep(&type_contract)
+        .build();
+
+    // rebuild the same transaction by hand, reusing the scenario's input
+    let mut tx = TransactionBuilder::default().build();
+    tx = tx.as_advanced_builder().input(scenario_tx.inputs().get(0).unwrap()).build();
+    tx = ct.add_outpoint(tx, always_success.clone(), Some(type_contract.clone()), &output_token_cell, Capacity::shannons(100));
+    tx = ct.set_output(tx, always_success.clone(), Some(type_contract.clone()), &inserted_cell, Capacity::shannons(100), 0);
+    tx = ct.add_contract_cell_dep(tx, &type_contract);
+    tx = ct.context.complete_tx(tx);
+
+    assert_eq!(tx.outputs().as_slice(), scenario_tx.outputs().as_slice());
+    assert_eq!(tx.outputs_data().as_slice(), scenario_tx.outputs_data().as_slice());
+    assert_eq!(tx.cell_deps().as_slice(), scenario_tx.cell_deps().as_slice());
+    ct.context.should_be_passed(&scenario_tx, 1000000).unwrap();
+}
+
+#[test]
+fn test_occupied_capacity() {
+    let input_token_cell = XUDTDataCell::new([1; 32], XUDTData { amount: 2000 });
+    let output_token_cell = XUDTDataCell::new([1; 32], XUDTData { amount: 2000 });
+
+    let mut ct = ContractUtil::new();
+    let type_contract = ct.deploy_contract("XUDT");
+    let always_success = ct.alway_contract.clone();
+
+    // lock: 32 code hash + 1 hash type + 1 arg, type: 32 + 1 + 32 args, 16 bytes data, 8 bytes capacity
+    let occupied = ct.occupied_capacity(&always_success, &Some(type_contract.clone()), &output_token_cell);
+    assert_eq!(occupied, Capacity::ckb(32 + 1 + 1 + 32 + 1 + 32 + 16 + 8));
+
+    let tx = ct.tx()
+        .input(&always_success, Some(&type_contract), &input_token_cell, Capacity::occupied_plus_ckb(1))
+        .output(&always_success, Some(&type_contract), &output_token_cell, Capacity::occupied())
+        .build();
+    let output_capacity: u64 = tx.outputs().get(0).unwrap().capacity().unpack();
+    assert_eq!(output_capacity, occupied.as_u64());
+    ct.context.should_be_passed(&tx, 1000000).unwrap();
+}
+
+#[test]
+fn test_witness_follows_inputs() {
+    let input_token_cell = XUDTDataCell::new([1; 32], XUDTData { amount: 1000 });
+    let mut witness_less_cell = XUDTDataCell::new([1; 32], XUDTData { amount: 1000 });
+    witness_less_cell.witness = None;
+    let output_token_cell = XUDTDataCell::new([1; 32], XUDTData { amount: 3000 });
+
+    let mut ct = ContractUtil::new();
+    let type_contract = ct.deploy_contract("XUDT");
+    let always_success = ct.alway_contract.clone();
+
+    let tx = ct.tx()
+        .input(&always_success, Some(&type_contract), &witness_less_cell, Capacity::shannons(100))
+        .input(&always_success, Some(&type_contract), &witness_less_cell, Capacity::shannons(100))
+        .input(&always_success, Some(&type_contract), &input_token_cell, Capacity::shannons(100))
+        .output(&always_success, Some(&type_contract), &output_token_cell, Capacity::shannons(100))
+        .build();
+    // only the last input carries a witness, the ones before are padded
+    assert_eq!(tx.witnesses().len(), 3);
+    assert!(tx.witnesses().get(0).unwrap().raw_data().is_empty());
+    assert_eq!(tx.witnesses().get(2).unwrap().raw_data().to_vec(), input_token_cell.get_witness().unwrap());
+
+    // all three inputs share the same lock, so they form one script group
+    let lock_hash = ct.input_lock_hash(&tx, 0);
+    assert_eq!(ct.group_input_indices(&tx, &lock_hash), vec![0, 1, 2]);
+    let tx = ct.tx_from(tx)
+        .group_witness(&lock_hash, &input_token_cell)
+        .build_raw();
+    assert_eq!(tx.witnesses().get(0).unwrap().raw_data().to_vec(), input_token_cell.get_witness().unwrap());
+    ct.context.should_be_passed(&tx, 1000000).unwrap();
+}
+
+#[test]
+fn test_capacity_arithmetic() {
+    assert_eq!(Capacity::ckb(2), Capacity::shannons(200_000_000));
+    assert_eq!(Capacity::ckb(2) - Capacity::shannons(1), Capacity::shannons(199_999_999));
+    assert_eq!(Capacity::occupied() + Capacity::ckb(1), Capacity::occupied_plus_ckb(1));
+    assert_eq!(Capacity::occupied_plus_ckb(3) - Capacity::occupied(), Capacity::ckb(3));
+    assert_eq!(Capacity::occupied_plus_shannons(5).resolve(100), Some(105));
+    assert_eq!(Capacity::shannons(5).resolve(100), Some(5));
+
+    assert_eq!(Capacity::shannons(u64::MAX).checked_add(Capacity::shannons(1)), None);
+    assert_eq!(Capacity::ZERO.checked_sub(Capacity::shannons(1)), None);
+    // the occupied capacity is not a number of shannons, it can't be counted twice or taken from a plain capacity
+    assert_eq!(Capacity::occupied().checked_add(Capacity::occupied()), None);
+    assert_eq!(Capacity::ckb(1).checked_sub(Capacity::occupied()), None);
+    assert_eq!(Capacity::occupied_plus_shannons(u64::MAX).resolve(1), None);
+}
+
+#[test]
+#[should_panic(expected = "capacity overflow")]
+fn test_capacity_ckb_overflow() {
+    Capacity::ckb(u64::MAX / 100_000_000 + 1);
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use crate::cell_message::cell::{Cell, CellEncodings, FieldEncodings};
+use crate::cell_message::encoding::Encoding;
+use crate::cell_message::molecule_cell::MoleculeCell;
+
+// xUDT type args: owner lock hash, flags and an optional extension, no molecule header
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct XudtArgs {
+    owner_lock_hash: [u8; 32],
+    flags: u32,
+    extension: Vec<u8>,
+}
+
+#[test]
+fn test_raw_encoding() {
+    let args = XudtArgs { owner_lock_hash: [1; 32], flags: 0x1000_0000, extension: vec![7, 8] };
+    let bytes = Encoding::Raw.encode(&args).unwrap();
+    assert_eq!(bytes, [[1u8; 32].to_vec(), 0x1000_0000u32.to_le_bytes().to_vec(), vec![7, 8]].concat());
+    assert_eq!(Encoding::Raw.decode::<XudtArgs>(&bytes).unwrap(), args);
+
+    // no extension
+    let bytes = [[1u8; 32].to_vec(), 0u32.to_le_bytes().to_vec()].concat();
+    assert_eq!(Encoding::Raw.decode::<XudtArgs>(&bytes).unwrap().extension, Vec::<u8>::new());
+    assert!(Encoding::Raw.decode::<XudtArgs>(&bytes[..35]).is_err());
+    assert!(Encoding::Raw.decode::<[u8; 20]>(&[0; 21]).is_err());
+}
+
+#[test]
+fn test_vec_encodings() {
+    let items: Vec<u8> = vec![1, 2, 3];
+    let bytes = Encoding::FixVec.encode(&items).unwrap();
+    assert_eq!(bytes, vec![3, 0, 0, 0, 1, 2, 3]);
+    assert_eq!(Encoding::FixVec.decode::<Vec<u8>>(&bytes).unwrap(), items);
+
+    let items: Vec<Vec<u8>> = vec![vec![1], vec![2, 3]];
+    let bytes = Encoding::DynVec.encode(&items).unwrap();
+    assert_eq!(bytes, vec![
+        23, 0, 0, 0, 12, 0, 0, 0, 17, 0, 0, 0,
+        1, 0, 0, 0, 1,
+        2, 0, 0, 0, 2, 3,
+    ]);
+    assert_eq!(Encoding::DynVec.decode::<Vec<Vec<u8>>>(&bytes).unwrap(), items);
+}
+
+#[test]
+fn test_custom_encoding() {
+    // a one byte length prefix
+    let encoding = Encoding::custom(
+        "length prefixed",
+        |raw| [vec![raw.len() as u8], raw].concat(),
+        |bytes| match bytes.split_first() {
+            Some((len, rest)) if *len as usize == rest.len() => Ok(rest.to_vec()),
+            _ => Err("bad length prefix".to_string()),
+        },
+    );
+    let bytes = encoding.encode(&[9u8; 4]).unwrap();
+    assert_eq!(bytes, vec![4, 9, 9, 9, 9]);
+    assert_eq!(encoding.decode::<[u8; 4]>(&bytes).unwrap(), [9; 4]);
+    assert_eq!(encoding.decode::<[u8; 4]>(&bytes[..4]).err().unwrap(), "bad length prefix");
+    assert_eq!(encoding.to_string(), "custom encoding `length prefixed`");
+}
+
+// lock and type args with no molecule header
+#[derive(Clone, PartialEq, Debug)]
+struct RawArgsEncodings;
+
+impl CellEncodings for RawArgsEncodings {
+    fn encodings() -> FieldEncodings {
+        FieldEncodings {
+            lock_arg: Encoding::Raw,
+            type_arg: Encoding::Raw,
+            ..FieldEncodings::default()
+        }
+    }
+}
+
+#[test]
+fn test_cell_with_raw_args() {
+    type XudtArgsCell = MoleculeCell<Vec<u8>, XudtArgs, u128, [u8; 4], RawArgsEncodings>;
+    let args = XudtArgs { owner_lock_hash: [3; 32], flags: 0, extension: vec![] };
+    let cell = XudtArgsCell::new(vec![1, 2, 3], Some(args), 1000, None);
+    assert_eq!(cell.get_lock_arg(), vec![1, 2, 3]);
+    assert_eq!(cell.get_type_arg().unwrap().len(), 36);
+
+    let decoded = XudtArgsCell::from_arg(cell.get_lock_arg(), cell.get_type_arg(), cell.get_data(), cell.get_witness());
+    assert_eq!(decoded, cell);
+}
@@ -1,22 +1,16 @@
+use crate::capacity::Capacity;
 use crate::ContractUtil;
 use ckb_testtool::{
     ckb_hash::blake2b_256,
-    ckb_types::{core::TransactionBuilder, prelude::*},
+    ckb_types::{core::{TransactionBuilder, TransactionView}, prelude::*},
 };
-use musig2::{
-    CompactSignature, FirstRound, KeyAggContext, PartialSignature, SecNonceSpices,
-};
-use secp256k1::{
-    rand::{self, RngCore},
-    PublicKey, Secp256k1, SecretKey,
-};
-use sha2::{Digest};
-use crate::cell_message::cell::MoleculeStructFlag;
 use crate::cells::funding_lock::{FundingCell, FundingWitness};
-use crate::cells::funding_lock_err::{FundingErrCell, FundingErrWitness};
-use crate::prelude::ContextExt;
-
-const EMPTY_WITNESS_ARGS: [u8; 16] = [16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0];
+use crate::cell_message::error::CellField;
+use crate::cell_message::malformed::MalformedCell;
+use crate::cell_message::witness_args::EmptyWitnessArgs;
+use crate::fiber::funding::FundingSigner;
+use crate::prelude::{ContextExt, ScriptKind};
+use crate::script_error::funding_lock::FundingLockError;
 
 #[test]
 fn test_funding_lock() {
@@ -24,119 +18,126 @@ fn test_funding_lock() {
     let funding_lock_contract = ct.deploy_contract("funding-lock");
     let auth_contract = ct.deploy_contract("auth");
 
+    let signer = FundingSigner::random(2);
+    let mut fc = FundingCell::new(signer.lock_arg(), None, 0, None);
+    let tx = TransactionBuilder::default().build();
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
 
-    // generate two random secret keys
-    let sec_key_1 = SecretKey::new(&mut rand::thread_rng());
-    let sec_key_2 = SecretKey::new(&mut rand::thread_rng());
+    let tx = ct.context.complete_tx(tx);
 
-    // public key aggregation
-    let secp256k1 = Secp256k1::new();
-    let pub_key_1 = sec_key_1.public_key(&secp256k1);
-    let pub_key_2 = sec_key_2.public_key(&secp256k1);
-    let key_agg_ctx = KeyAggContext::new(vec![pub_key_1, pub_key_2]).unwrap();
-    let aggregated_pub_key: PublicKey = key_agg_ctx.aggregated_pubkey();
-    let x_only_pub_key = aggregated_pub_key.x_only_public_key().0.serialize();
+    // sign and add witness
+    fc.witness = Some(signer.witness(&tx));
+    let tx = ct.set_witness(tx, 0, &fc);
 
-    // prepare scripts
-    let pub_key_hash = blake2b_256(x_only_pub_key);
+    // run
+    let cycles = ct.context
+        .should_be_passed(&tx, 100000000).unwrap();
+    println!("consume cycles: {}", cycles);
+}
 
+#[test]
+fn test_funding_signer_message() {
+    let mut ct = ContractUtil::new();
+    let funding_lock_contract = ct.deploy_contract("funding-lock");
 
-    let mut fc = FundingCell {
-        lock_arg: <[u8; 20]>::try_from(pub_key_hash[0..20].to_vec().as_slice()).unwrap(),
-        type_arg: None,
-        data: 0,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
+    let signer = FundingSigner::random(2).with_version(3);
+    let fc = FundingCell::new(signer.lock_arg(), None, 0, None);
     let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, 500);
-    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, 500);
-    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
-
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
     let tx = ct.context.complete_tx(tx);
 
-    // sign and add witness
     let tx_hash: [u8; 32] = tx.hash().as_slice().try_into().unwrap();
+    let funding_out_point = tx.inputs().get(0).unwrap().previous_output();
+    let message = blake2b_256([
+        3u64.to_le_bytes().to_vec(),
+        funding_out_point.as_slice().to_vec(),
+        tx_hash.to_vec(),
+    ].concat());
+    assert_eq!(signer.message(&tx), message);
+    assert_eq!(signer.lock_arg(), blake2b_256(signer.pubkey())[0..20]);
+
+    let witness = signer.witness(&tx);
+    assert_eq!(witness.version, 3);
+    assert_eq!(witness.funding_out_point, funding_out_point.as_slice());
+    assert_eq!(witness.pubkey, signer.pubkey());
+}
 
-    let version = 0u64.to_le_bytes();
-    let binding = tx.clone().inputs().get(0).unwrap().previous_output();
-    let funding_out_point = binding.as_slice();
+// a tx spending the funding cell of `lock`, whose witness is made by `witness`
+fn funding_spend(ct: &mut ContractUtil, lock: &FundingSigner, witness: impl FnOnce(&TransactionView) -> FundingWitness) -> TransactionView {
+    let funding_lock_contract = ct.deploy_contract("funding-lock");
+    let auth_contract = ct.deploy_contract("auth");
 
-    let message = blake2b_256(
-        [
-            version.to_vec(),
-            funding_out_point.to_vec(),
-            tx_hash.to_vec(),
-        ]
-            .concat(),
-    );
-
-    let mut first_round_1 = {
-        let mut nonce_seed = [0u8; 32];
-        rand::rngs::OsRng.fill_bytes(&mut nonce_seed);
-
-        FirstRound::new(
-            key_agg_ctx.clone(),
-            nonce_seed,
-            0,
-            SecNonceSpices::new()
-                .with_seckey(sec_key_1)
-                .with_message(&message),
-        )
-            .unwrap()
-    };
-
-    let mut first_round_2 = {
-        let mut nonce_seed = [0u8; 32];
-        rand::rngs::OsRng.fill_bytes(&mut nonce_seed);
-
-        FirstRound::new(
-            key_agg_ctx,
-            nonce_seed,
-            1,
-            SecNonceSpices::new()
-                .with_seckey(sec_key_2)
-                .with_message(&message),
-        )
-            .unwrap()
-    };
-
-    first_round_1
-        .receive_nonce(1, first_round_2.our_public_nonce())
-        .unwrap();
-    first_round_2
-        .receive_nonce(0, first_round_1.our_public_nonce())
-        .unwrap();
-
-    let mut second_round_1 = first_round_1.finalize(sec_key_1, &message).unwrap();
-    let mut second_round_2 = first_round_2.finalize(sec_key_2, &message).unwrap();
-    let signature_1: PartialSignature = second_round_1.our_signature();
-    let signature_2: PartialSignature = second_round_2.our_signature();
-
-    second_round_1.receive_signature(1, signature_2).unwrap();
-    let aggregated_signature_1: CompactSignature = second_round_1.finalize().unwrap();
-    second_round_2.receive_signature(0, signature_1).unwrap();
-    let aggregated_signature_2: CompactSignature = second_round_2.finalize().unwrap();
-
-    assert_eq!(aggregated_signature_1, aggregated_signature_2);
-    // println!("signature: {:?}", aggregated_signature_1.to_bytes());
-
-
-    fc.witness = Some(FundingWitness {
-        empty_witness_args: EMPTY_WITNESS_ARGS,
-        version: 0u64,
-        funding_out_point: <[u8; 36]>::try_from(&funding_out_point[0..36]).unwrap(),
-        pubkey: x_only_pub_key,
-        signature: aggregated_signature_1.into(),
-    });
+    let mut fc = lock.cell();
+    let tx = TransactionBuilder::default().build();
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_contract_cell_dep(tx, &auth_contract);
+    let tx = ct.context.complete_tx(tx);
 
-    let tx = ct.replace_output(tx, funding_lock_contract, None, &fc, 500, 0);
+    fc.witness = Some(witness(&tx));
+    ct.set_witness(tx, 0, &fc)
+}
 
+#[test]
+fn test_funding_lock_n_parties() {
+    for parties in [1, 3, 5] {
+        let mut ct = ContractUtil::new();
+        let signer = FundingSigner::random(parties);
+        let tx = funding_spend(&mut ct, &signer, |tx| signer.witness(tx));
+        ct.context.should_be_passed(&tx, 100000000).unwrap();
+    }
+}
 
-    // run
-    let cycles = ct.context
-        .should_be_passed(&tx, 100000000).unwrap();
-    println!("consume cycles: {}", cycles);
+#[test]
+fn test_funding_lock_sorted_keys() {
+    let signer = FundingSigner::random(3);
+    let keys = signer.secret_keys().to_vec();
+    let reversed: Vec<_> = keys.iter().rev().cloned().collect();
+    assert_eq!(FundingSigner::sorted(keys).lock_arg(), FundingSigner::sorted(reversed.clone()).lock_arg());
+
+    // locked by one order, signed by the other: same aggregated key
+    let mut ct = ContractUtil::new();
+    let tx = funding_spend(&mut ct, &FundingSigner::sorted(reversed), |tx| FundingSigner::sorted(signer.secret_keys().to_vec()).witness(tx));
+    ct.context.should_be_passed(&tx, 100000000).unwrap();
+}
+
+#[test]
+fn test_funding_lock_key_order() {
+    // the aggregated key depends on the key order, every party signing in another order
+    // gives a valid signature of a key that is not the locked one
+    let signer = FundingSigner::random(3);
+    for order in [[0, 2, 1], [2, 1, 0]] {
+        let reordered = signer.with_key_order(&order);
+        assert_ne!(reordered.lock_arg(), signer.lock_arg());
+
+        let mut ct = ContractUtil::new();
+        let tx = funding_spend(&mut ct, &signer, |tx| reordered.witness(tx));
+        ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::AuthMismatched);
+    }
+
+    // and the reordered key unlocks its own cell
+    let reordered = signer.with_key_order(&[1, 0, 2]);
+    let mut ct = ContractUtil::new();
+    let tx = funding_spend(&mut ct, &reordered, |tx| reordered.witness(tx));
+    ct.context.should_be_passed(&tx, 100000000).unwrap();
+}
+
+#[test]
+fn test_funding_lock_subset_of_keys() {
+    let signer = FundingSigner::random(3);
+    let subset = signer.subset(&[0, 1]);
+
+    // the subset signs for its own aggregated key
+    let mut ct = ContractUtil::new();
+    let tx = funding_spend(&mut ct, &signer, |tx| subset.witness(tx));
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::AuthMismatched);
+
+    // the subset signature presented with the locked key
+    let mut ct = ContractUtil::new();
+    let tx = funding_spend(&mut ct, &signer, |tx| FundingWitness { pubkey: signer.pubkey(), ..subset.witness(tx) });
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::AuthSchnorrError);
 }
 
 #[test]
@@ -146,29 +147,15 @@ fn test_multiple_inputs_err() {
     let auth_contract = ct.deploy_contract("auth");
 
 
-    let fc = FundingCell {
-        lock_arg: [0; 20],
-        type_arg: None,
-        data: 0,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
+    let fc = FundingCell::new([0; 20], None, 0, None);
     let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, 500);
-    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, 500);
-    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, 500);
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
     let tx = ct.add_contract_cell_dep(tx, &auth_contract);
 
     let tx = ct.context.complete_tx(tx);
-    match ct.context
-        .should_be_failed(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            assert!(err.to_string().contains("code 5"))
-        }
-    };
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::MultipleInputs);
 }
 
 #[test]
@@ -178,35 +165,22 @@ fn test_empty_witness_args_error() {
     let auth_contract = ct.deploy_contract("auth");
 
 
-    let fc = FundingCell {
-        lock_arg: [0; 20],
-        type_arg: None,
-        data: 0,
-        witness: Some(FundingWitness {
-            empty_witness_args: [1; 16],
-            version: 0u64,
-            funding_out_point: [1; 36],
-            pubkey: [1; 32],
-            signature: [1; 64],
-        }),
-        struct_flag: MoleculeStructFlag::default(),
-    };
+    let fc = FundingCell::new([0; 20], None, 0, Some(FundingWitness {
+        witness_args: EmptyWitnessArgs::default(),
+        version: 0u64,
+        funding_out_point: [1; 36],
+        pubkey: [1; 32],
+        signature: [1; 64],
+    }));
+    // the full size of the WitnessArgs is no longer 16
+    let fc = MalformedCell::from_cell(&fc).flip_byte(CellField::Witness, 0);
     let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, 500);
-    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, 500);
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
     let tx = ct.add_contract_cell_dep(tx, &auth_contract);
 
     let tx = ct.context.complete_tx(tx);
-    match ct.context
-        .should_be_failed(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 7"))
-        }
-    };
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::EmptyWitnessArgsError);
 }
 
 #[test]
@@ -216,29 +190,14 @@ fn test_witness_len_zero_error() {
     let auth_contract = ct.deploy_contract("auth");
 
 
-    let fc = FundingCell {
-        lock_arg: [0; 20],
-        type_arg: None,
-        data: 0,
-        witness: None,
-        struct_flag: MoleculeStructFlag::default(),
-    };
+    let fc = FundingCell::new([0; 20], None, 0, None);
     let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, 500);
-    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, 500);
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
     let tx = ct.add_contract_cell_dep(tx, &auth_contract);
 
     let tx = ct.context.complete_tx(tx);
-    match ct.context
-        .should_be_failed(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code -1"))
-        }
-    };
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::Panic);
 }
 
 #[test]
@@ -248,36 +207,21 @@ fn test_witness_len_error() {
     let auth_contract = ct.deploy_contract("auth");
 
 
-    let fc = FundingErrCell {
-        lock_arg: [0; 20],
-        type_arg: None,
-        data: 0,
-        witness: Some(FundingErrWitness {
-            empty_witness_args: EMPTY_WITNESS_ARGS,
-            version: 0u64,
-            funding_out_point: [1; 36],
-            pubkey: [1; 32],
-            signature: [1; 64],
-            err:[1;32]
-        }),
-        struct_flag: MoleculeStructFlag::default(),
-    };
+    let fc = FundingCell::new([0; 20], None, 0, Some(FundingWitness {
+        witness_args: EmptyWitnessArgs::default(),
+        version: 0u64,
+        funding_out_point: [1; 36],
+        pubkey: [1; 32],
+        signature: [1; 64],
+    }));
+    let fc = MalformedCell::from_cell(&fc).extend(CellField::Witness, &[1; 32]);
     let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, 500);
-    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, 500);
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
     let tx = ct.add_contract_cell_dep(tx, &auth_contract);
 
     let tx = ct.context.complete_tx(tx);
-    match ct.context
-        .should_be_failed(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 6"))
-        }
-    };
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::WitnessLenError);
 }
 
 #[test]
@@ -287,35 +231,20 @@ fn test_funding_out_point_error() {
     let auth_contract = ct.deploy_contract("auth");
 
 
-    let fc = FundingCell {
-        lock_arg: [0; 20],
-        type_arg: None,
-        data: 0,
-        witness: Some(FundingWitness {
-            empty_witness_args: EMPTY_WITNESS_ARGS,
-            version: 0u64,
-            funding_out_point: [1; 36],
-            pubkey: [1; 32],
-            signature: [1; 64],
-        }),
-        struct_flag: MoleculeStructFlag::default(),
-    };
+    let fc = FundingCell::new([0; 20], None, 0, Some(FundingWitness {
+        witness_args: EmptyWitnessArgs::default(),
+        version: 0u64,
+        funding_out_point: [1; 36],
+        pubkey: [1; 32],
+        signature: [1; 64],
+    }));
     let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, 500);
-    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, 500);
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
     let tx = ct.add_contract_cell_dep(tx, &auth_contract);
 
     let tx = ct.context.complete_tx(tx);
-    match ct.context
-        .should_be_failed(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 8"))
-        }
-    };
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::FundingOutPointError);
 }
 
 #[test]
@@ -324,54 +253,34 @@ fn test_exec_cell_error() {
     let funding_lock_contract = ct.deploy_contract("funding-lock");
     let auth_contract = ct.deploy_contract("auth");
 
-    let fc = FundingCell {
-        lock_arg: [0; 20],
-        type_arg: None,
-        data: 0,
-        witness: Some(FundingWitness {
-            empty_witness_args: EMPTY_WITNESS_ARGS,
-            version: 0u64,
-            funding_out_point: [1; 36],
-            pubkey: [1; 32],
-            signature: [1; 64],
-        }),
-        struct_flag: MoleculeStructFlag::default(),
-    };
+    let fc = FundingCell::new([0; 20], None, 0, Some(FundingWitness {
+        witness_args: EmptyWitnessArgs::default(),
+        version: 0u64,
+        funding_out_point: [1; 36],
+        pubkey: [1; 32],
+        signature: [1; 64],
+    }));
     let tx = TransactionBuilder::default().build();
-    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, 500);
+    let tx = ct.add_input(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
 
     let binding = tx.clone().inputs().get(0).unwrap().previous_output();
     let funding_out_point = binding.as_slice();
 
-    let fc = FundingCell {
-        lock_arg: [0; 20],
-        type_arg: None,
-        data: 0,
-        witness: Some(FundingWitness {
-            empty_witness_args: EMPTY_WITNESS_ARGS,
-            version: 0u64,
-            funding_out_point: <[u8; 36]>::try_from(&funding_out_point[0..36]).unwrap(),
-            pubkey: [1; 32],
-            signature: [1; 64],
-        }),
-        struct_flag: MoleculeStructFlag::default(),
-    };
-
-
-    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, 500);
+    let fc = FundingCell::new([0; 20], None, 0, Some(FundingWitness {
+        witness_args: EmptyWitnessArgs::default(),
+        version: 0u64,
+        funding_out_point: <[u8; 36]>::try_from(&funding_out_point[0..36]).unwrap(),
+        pubkey: [1; 32],
+        signature: [1; 64],
+    }));
+
+
+    let tx = ct.add_outpoint(tx, funding_lock_contract.clone(), None, &fc, Capacity::shannons(500));
+    let tx = ct.set_witness(tx, 0, &fc);
     let tx = ct.add_contract_cell_dep(tx, &auth_contract);
 
     let tx = ct.context.complete_tx(tx);
-    match ct.context
-        .should_be_failed(&tx, 100000000) {
-        Ok(_) => {
-            assert!(false)
-        }
-        Err(err) => {
-            println!("err:{:?}", err);
-            assert!(err.to_string().contains("code 110"))
-        }
-    };
+    ct.context.should_fail_with(&tx, 100000000, ScriptKind::Lock, 0, FundingLockError::AuthSchnorrError);
 }
 
 #[test]
@@ -0,0 +1,161 @@
+use ckb_testtool::ckb_crypto::secp::Generator;
+use ckb_testtool::ckb_types::packed::CellDep;
+use ckb_testtool::ckb_types::prelude::{Builder, Entity};
+use crate::capacity::Capacity;
+use crate::cells::demo::Demo;
+use crate::chain::ledger::LedgerError;
+use crate::chain::since::Since;
+use crate::fiber::commitment::{sign, CommitmentScript};
+use crate::fiber::funding::FundingSigner;
+use crate::fiber::settlement::{CommitmentState, Unlock};
+use crate::ContractUtil;
+
+const MAX_CYCLES: u64 = 100_000_000;
+
+#[test]
+fn test_chained_txs() {
+    let mut ct = ContractUtil::new();
+    let always_success = ct.alway_contract.clone();
+    let tx1 = ct.tx()
+        .input(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(600))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(400))
+        .build();
+    let input = tx1.inputs().get(0).unwrap().previous_output();
+    assert!(ct.ledger.is_live(&input));
+
+    let outputs = ct.commit_tx(&tx1, MAX_CYCLES).unwrap();
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs[1].tx_hash(), tx1.hash());
+    assert!(!ct.ledger.is_live(&input));
+    assert_eq!(ct.ledger.spent_by(&input), Some(&tx1.hash()));
+    assert!(outputs.iter().all(|out_point| ct.ledger.is_live(out_point)));
+
+    // the outputs are real cells the next tx spends
+    let tx2 = ct.tx()
+        .spend(&outputs[0])
+        .spend(&outputs[1])
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .build();
+    ct.commit_tx(&tx2, MAX_CYCLES).unwrap();
+
+    assert_eq!(ct.commit_tx(&tx1, MAX_CYCLES), Err(LedgerError::DeadInput { index: 0, spent_by: tx1.hash() }));
+    assert_eq!(ct.commit_tx(&tx2, MAX_CYCLES), Err(LedgerError::DeadInput { index: 0, spent_by: tx2.hash() }));
+}
+
+#[test]
+fn test_duplicate_input() {
+    let mut ct = ContractUtil::new();
+    let always_success = ct.alway_contract.clone();
+    let tx = ct.tx()
+        .input(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .build();
+    let input = tx.inputs().get(0).unwrap().previous_output();
+    let tx = ct.tx_from(tx).spend(&input).build();
+    assert_eq!(ct.commit_tx(&tx, MAX_CYCLES), Err(LedgerError::DuplicateInput { index: 1 }));
+    assert!(ct.ledger.is_live(&input));
+}
+
+#[test]
+fn test_dead_cell_dep() {
+    let mut ct = ContractUtil::new();
+    let always_success = ct.alway_contract.clone();
+    let tx1 = ct.tx()
+        .input(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .build();
+    let spent = tx1.inputs().get(0).unwrap().previous_output();
+    ct.commit_tx(&tx1, MAX_CYCLES).unwrap();
+
+    let tx2 = ct.tx()
+        .input(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .build();
+    let cell_deps = tx2.cell_deps().len();
+    let tx2 = tx2.as_advanced_builder()
+        .cell_dep(CellDep::new_builder().out_point(spent).build())
+        .build();
+    assert_eq!(ct.commit_tx(&tx2, MAX_CYCLES), Err(LedgerError::DeadCellDep { index: cell_deps, spent_by: tx1.hash() }));
+}
+
+#[test]
+fn test_failed_tx_spends_nothing() {
+    let mut ct = ContractUtil::new();
+    let funding_lock_contract = ct.deploy_contract("funding-lock");
+    let auth_contract = ct.deploy_contract("auth");
+    let signer = FundingSigner::random(2);
+    // no witness
+    let tx = ct.tx()
+        .input(&funding_lock_contract, None, &signer.cell(), Capacity::shannons(1000))
+        .output(&funding_lock_contract, None, &signer.cell(), Capacity::shannons(1000))
+        .dep(&auth_contract)
+        .build();
+    assert!(matches!(ct.commit_tx(&tx, MAX_CYCLES), Err(LedgerError::Verification(_))));
+    assert!(ct.ledger.is_live(&tx.inputs().get(0).unwrap().previous_output()));
+}
+
+#[test]
+fn test_immature_input() {
+    let mut ct = ContractUtil::new();
+    let always_success = ct.alway_contract.clone();
+    let tx1 = ct.tx()
+        .input(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .build();
+    let outputs = ct.commit_tx(&tx1, MAX_CYCLES).unwrap();
+
+    // the scripts pass, the chain is 10 blocks short
+    let tx2 = ct.tx()
+        .spend_with_since(&outputs[0], Since::relative_block_number(10))
+        .output(&always_success, None, &Demo::default(), Capacity::shannons(1000))
+        .build();
+    assert!(matches!(ct.commit_tx(&tx2, MAX_CYCLES), Err(LedgerError::ImmatureInput(_))));
+    assert!(ct.ledger.is_live(&outputs[0]));
+
+    ct.advance_blocks(10);
+    ct.commit_tx(&tx2, MAX_CYCLES).unwrap();
+    assert!(!ct.ledger.is_live(&outputs[0]));
+}
+
+// funding -> commitment -> revocation, each tx spending the output of the previous one
+#[test]
+fn test_funding_commitment_settlement_flow() {
+    let mut ct = ContractUtil::new();
+    let funding_lock_contract = ct.deploy_contract("funding-lock");
+    let commitment_lock_contract = ct.deploy_contract("commitment-lock");
+    let auth_contract = ct.deploy_contract("auth");
+    let always_success = ct.alway_contract.clone();
+
+    let mut generator = Generator::new();
+    let local_delay_key = generator.gen_keypair();
+    let revocation_key = generator.gen_keypair();
+    let script = CommitmentScript::new(Since::relative_epoch(10, 1, 2).as_u64(), &local_delay_key.1, &revocation_key.1);
+    let state = CommitmentState::ckb(script, Capacity::shannons(1000));
+
+    let signer = FundingSigner::random(2);
+    let mut funding_cell = signer.cell();
+    let funding_tx = ct.tx()
+        .input(&funding_lock_contract, None, &funding_cell, Capacity::shannons(1000))
+        .output(&commitment_lock_contract, None, &state.cell(), state.capacity)
+        .dep(&auth_contract)
+        .build();
+    funding_cell.witness = Some(signer.witness(&funding_tx));
+    let funding_tx = ct.set_witness(funding_tx, 0, &funding_cell);
+    let commitment = ct.commit_tx(&funding_tx, MAX_CYCLES).unwrap();
+
+    let settlement = state.settle(&Unlock::Revoke);
+    let revocation_tx = ct.tx()
+        .spend_with_since(&commitment[0], settlement.since)
+        .output(&always_success, None, &state.cell(), state.capacity)
+        .dep(&auth_contract)
+        .build();
+    let mut commitment_cell = state.cell();
+    commitment_cell.witness = Some(settlement.witness(sign(&revocation_key.0, &revocation_tx)));
+    let revocation_tx = ct.set_witness(revocation_tx, 0, &commitment_cell);
+    ct.commit_tx(&revocation_tx, MAX_CYCLES).unwrap();
+
+    // neither the funding cell nor the commitment cell can be spent again
+    assert_eq!(ct.commit_tx(&funding_tx, MAX_CYCLES), Err(LedgerError::DeadInput { index: 0, spent_by: funding_tx.hash() }));
+    assert_eq!(ct.commit_tx(&revocation_tx, MAX_CYCLES), Err(LedgerError::DeadInput { index: 0, spent_by: revocation_tx.hash() }));
+}
@@ -0,0 +1,91 @@
+use crate::cell_message::cell::Cell;
+use crate::cell_message::encoding::Encoding;
+use crate::cell_message::error::CellField;
+use crate::cell_message::malformed::MalformedCell;
+use crate::cell_message::witness_args::EmptyWitnessArgs;
+use crate::cells::commitment_lock::CommitmentUdtCell;
+use crate::cells::funding_lock::{FundingCell, FundingWitness};
+use crate::cells::xudt_data::{XUDTData, XUDTDataCell};
+
+fn funding_cell() -> FundingCell {
+    FundingCell::new([2; 20], None, 0, Some(FundingWitness {
+        witness_args: EmptyWitnessArgs::default(),
+        version: 0,
+        funding_out_point: [3; 36],
+        pubkey: [4; 32],
+        signature: [5; 64],
+    }))
+}
+
+#[test]
+fn test_struct_field_layout() {
+    let cell = funding_cell().malformed();
+    let layout = cell.layout(CellField::Witness).unwrap();
+    assert_eq!(layout.ranges, vec![0..16, 16..24, 24..60, 60..92, 92..156]);
+    assert!(!layout.table);
+
+    // without the pubkey
+    let dropped = cell.clone().drop_field(CellField::Witness, 3);
+    let witness = dropped.witness.clone().unwrap();
+    assert_eq!(witness.len(), 124);
+    assert_eq!(witness[60..], [5u8; 64]);
+    assert_eq!(dropped.description, "witness field 3 dropped");
+    assert!(dropped.layout(CellField::Witness).is_none());
+}
+
+#[test]
+fn test_derived_cell_layouts() {
+    // not a MoleculeCell, the derive gives the layouts
+    let cell = CommitmentUdtCell::new([2; 20], Some([3; 32]), 5, None).malformed();
+    assert_eq!(cell.layout(CellField::LockArg).unwrap().ranges, vec![0..20]);
+    assert_eq!(cell.layout(CellField::TypeArg).unwrap().ranges, vec![0..32]);
+    assert_eq!(cell.layout(CellField::Data).unwrap().ranges, vec![0..16]);
+    assert!(cell.layout(CellField::Witness).is_none());
+}
+
+#[test]
+fn test_byte_mutations() {
+    let fc = funding_cell();
+    let cell = MalformedCell::from_cell(&fc)
+        .extend(CellField::LockArg, &[0; 2])
+        .truncate(CellField::Witness, 4)
+        .flip_byte(CellField::Data, 0);
+    assert_eq!(cell.get_lock_arg(), [[2u8; 20].to_vec(), vec![0, 0]].concat());
+    assert_eq!(cell.get_witness().unwrap(), fc.get_witness().unwrap()[..152].to_vec());
+    assert_eq!(cell.get_data(), vec![0xff]);
+    assert_eq!(cell.get_type_arg(), None);
+    assert_eq!(cell.description, "lock_arg extended by 2 bytes, witness truncated by 4 bytes, data byte 0 flipped");
+}
+
+#[test]
+fn test_table_field_layout() {
+    let xudt = XUDTDataCell::new([1; 32], XUDTData { amount: 1 });
+    let cell = MalformedCell::from_cell(&xudt)
+        .with_layout(CellField::Witness, &EmptyWitnessArgs::default(), &Encoding::Table);
+    assert_eq!(cell.layout(CellField::Witness).unwrap().ranges, vec![16..16, 16..16, 16..16]);
+
+    // a WitnessArgs without output_type, the header stays consistent
+    let dropped = cell.drop_field(CellField::Witness, 2);
+    assert_eq!(dropped.witness.unwrap(), vec![12, 0, 0, 0, 12, 0, 0, 0, 12, 0, 0, 0]);
+}
+
+#[test]
+fn test_variants() {
+    let cell = funding_cell().malformed();
+    let variants = cell.variants(CellField::Witness);
+    // truncate, extend, 2 flips and 5 dropped fields
+    assert_eq!(variants.len(), 9);
+    assert!(variants.iter().all(|variant| variant.witness != cell.witness));
+    // no type args, lock args and data are single fields (data is 1 byte, flipped once)
+    assert_eq!(cell.variants(CellField::TypeArg).len(), 0);
+    assert_eq!(cell.all_variants().len(), 9 + 4 + 3);
+}
+
+#[test]
+fn test_malformed_cell_from_arg() {
+    let fc = funding_cell();
+    let cell = MalformedCell::from_arg(fc.get_lock_arg(), fc.get_type_arg(), fc.get_data(), fc.get_witness());
+    assert_eq!(cell, MalformedCell::from_cell(&fc));
+    assert!(cell.description.is_empty());
+    assert!(cell.layout(CellField::Witness).is_none());
+}
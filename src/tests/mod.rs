@@ -1,4 +1,14 @@
 mod xudt;
 mod contract_util;
 mod funding_lock;
-mod commitment_lock;
\ No newline at end of file
+mod commitment_lock;
+mod witness_args;
+mod script_error;
+mod cell_derive;
+mod encoding;
+mod malformed;
+mod since;
+mod clock;
+mod ledger;
+mod channel;
+mod consensus;
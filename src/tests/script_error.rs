@@ -0,0 +1,70 @@
+use crate::prelude::{ContractError, ScriptFailure, ScriptKind};
+use crate::script_error::commitment_lock::CommitmentLockError;
+use crate::script_error::funding_lock::FundingLockError;
+
+const CODE_HASH: &str = "9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8";
+
+fn message(source: &str, code: i8) -> String {
+    format!(
+        "TransactionScriptError {{ source: {source}, cause: ValidationFailure: see error code {code} on page https://nervosnetwork.github.io/ckb-script-error-codes/by-data-hash/{CODE_HASH}.html#{code} }}"
+    )
+}
+
+#[test]
+fn test_parse_lock_failure() {
+    let failure = ScriptFailure::parse_message(&message("Inputs[1].Lock", 5)).unwrap();
+    assert_eq!(failure.kind, ScriptKind::Lock);
+    assert_eq!(failure.index, 1);
+    assert_eq!(failure.code, 5);
+    assert_eq!(failure.code_hash, CODE_HASH);
+    assert!(!failure.by_type_hash);
+    assert!(failure.matches(ScriptKind::Lock, 1, 5));
+    assert_eq!(failure.to_string(), format!("Inputs[1].Lock failed with code 5 (code hash by data: 0x{CODE_HASH})"));
+}
+
+#[test]
+fn test_parse_does_not_confuse_codes() {
+    // "code 5" is a prefix of "code 56", the parsed failure must not match both
+    let failure = ScriptFailure::parse_message(&message("Inputs[0].Lock", 56)).unwrap();
+    assert_eq!(failure.code, 56);
+    assert!(!failure.matches(ScriptKind::Lock, 0, 5));
+}
+
+#[test]
+fn test_parse_type_failures() {
+    let failure = ScriptFailure::parse_message(&message("Outputs[2].Type", -1)).unwrap();
+    assert!(failure.matches(ScriptKind::OutputType, 2, -1));
+    let failure = ScriptFailure::parse_message(&message("Inputs[0].Type", 8)).unwrap();
+    assert!(failure.matches(ScriptKind::InputType, 0, 8));
+}
+
+#[test]
+fn test_parse_non_script_error() {
+    assert_eq!(ScriptFailure::parse_message("ExceededMaximumCycles: expect cycles <= 1000"), None);
+}
+
+#[test]
+fn test_contract_error_registry() {
+    let failure = ScriptFailure::parse_message(&message("Inputs[0].Lock", 14)).unwrap();
+    assert_eq!(failure.error::<CommitmentLockError>(), Some(CommitmentLockError::OutputLockError));
+    assert_eq!(failure.error::<FundingLockError>(), None);
+    assert!(failure.describe::<CommitmentLockError>().ends_with("[CommitmentLockError::OutputLockError]"));
+
+    assert_eq!(FundingLockError::from_code(-1), Some(FundingLockError::Panic));
+    assert_eq!(FundingLockError::AuthSchnorrError.code(), 110);
+    assert_eq!(CommitmentLockError::AuthMismatched.code(), 101);
+    assert_eq!(CommitmentLockError::from_code(19), None);
+}
+
+#[test]
+fn test_contract_error_codes_round_trip() {
+    for code in i8::MIN..=i8::MAX {
+        if let Some(error) = FundingLockError::from_code(code) {
+            assert_eq!(error.code(), code);
+        }
+        if let Some(error) = CommitmentLockError::from_code(code) {
+            assert_eq!(error.code(), code);
+        }
+    }
+    assert_eq!(CommitmentLockError::from_code(101).unwrap().name(), "CommitmentLockError::AuthMismatched");
+}
@@ -0,0 +1,45 @@
+use ckb_std::since::{EpochNumberWithFraction, Since as CkbSince};
+use crate::chain::since::{Since, SinceMetric};
+
+#[test]
+fn test_since_layout() {
+    assert_eq!(Since::relative_epoch(10, 1, 2).as_u64(), CkbSince::from_epoch(EpochNumberWithFraction::new(10, 1, 2), false).as_u64());
+    assert_eq!(Since::absolute_epoch(10, 1, 2).as_u64(), CkbSince::from_epoch(EpochNumberWithFraction::new(10, 1, 2), true).as_u64());
+    assert_eq!(Since::absolute_timestamp(1711976400).as_u64(), CkbSince::from_timestamp(1711976400, true).unwrap().as_u64());
+    assert_eq!(Since::relative_timestamp(3600).as_u64(), CkbSince::from_timestamp(3600, false).unwrap().as_u64());
+    assert_eq!(Since::absolute_block_number(100).as_u64(), CkbSince::from_block_number(100, true).unwrap().as_u64());
+    assert_eq!(Since::relative_block_number(100).as_u64(), CkbSince::from_block_number(100, false).unwrap().as_u64());
+    assert_eq!(Since::NONE.as_u64(), 0);
+}
+
+#[test]
+fn test_since_metric() {
+    let since = Since::relative_epoch(10, 1, 2);
+    assert!(since.is_relative());
+    assert_eq!(since.metric(), Ok(SinceMetric::Epoch { number: 10, index: 1, length: 2 }));
+    assert_eq!(since.to_string(), "relative epoch 10 1/2");
+    assert_eq!(Since::absolute_timestamp(1711976400).metric(), Ok(SinceMetric::Timestamp(1711976400)));
+    assert!(!Since::absolute_timestamp(1711976400).is_relative());
+    assert!(Since::raw(0b11 << 61).metric().is_err());
+}
+
+#[test]
+fn test_since_check() {
+    // a relative epoch may have no fraction, an absolute one must have a length
+    assert!(Since::new(true, SinceMetric::Epoch { number: 1, index: 0, length: 0 }).is_ok());
+    assert!(Since::new(false, SinceMetric::Epoch { number: 1, index: 0, length: 0 }).is_err());
+    assert!(Since::new(true, SinceMetric::Epoch { number: 1, index: 2, length: 2 }).is_err());
+    assert!(Since::new(true, SinceMetric::Epoch { number: 1 << 24, index: 0, length: 1 }).is_err());
+    assert!(Since::new(false, SinceMetric::BlockNumber(1 << 56)).is_err());
+    assert!(Since::new(false, SinceMetric::Timestamp((1 << 56) - 1)).is_ok());
+
+    // raw values are not checked until asked
+    assert!(Since::raw(1 << 56).check().is_err());
+    assert!(Since::raw(Since::relative_epoch(10, 1, 2).as_u64()).check().is_ok());
+}
+
+#[test]
+#[should_panic(expected = "invalid since")]
+fn test_since_constructor_panics() {
+    Since::absolute_epoch(10, 3, 2);
+}
@@ -0,0 +1,76 @@
+use ckb_testtool::ckb_types::prelude::Entity;
+use serde::{Deserialize, Serialize};
+use serde_molecule::{from_slice, to_vec};
+use crate::cell_message::cell::Cell;
+use crate::cell_message::encoding::Encoding;
+use crate::cell_message::witness_args::{EmptyWitnessArgs, WitnessArgs, EMPTY_WITNESS_ARGS_LEN};
+use crate::cells::funding_lock::FundingWitness;
+use crate::cells::xudt_data::{XUDTData, XUDTDataCell};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+struct LockPayload {
+    pub pubkey_hash: [u8; 20],
+    pub nonce: u64,
+}
+
+#[test]
+fn test_empty_witness_args_encoding() {
+    let witness = to_vec(&EmptyWitnessArgs::default(), false).unwrap();
+    assert_eq!(witness, vec![16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0, 16, 0, 0, 0]);
+    assert_eq!(witness, EmptyWitnessArgs::default().to_packed().as_slice().to_vec());
+}
+
+#[test]
+fn test_typed_witness_args_matches_packed() {
+    let witness_args: WitnessArgs<LockPayload, [u8; 32], u128> = WitnessArgs::new(
+        Some(LockPayload { pubkey_hash: [7; 20], nonce: 42 }),
+        None,
+        Some(424242u128),
+    );
+    let witness = to_vec(&witness_args, false).unwrap();
+    let packed = witness_args.to_packed();
+    assert_eq!(witness, packed.as_slice().to_vec());
+
+    let lock = packed.lock().to_opt().unwrap().raw_data().to_vec();
+    assert_eq!(lock, [[7u8; 20].to_vec(), 42u64.to_le_bytes().to_vec()].concat());
+    assert!(packed.input_type().to_opt().is_none());
+    let output_type = packed.output_type().to_opt().unwrap().raw_data().to_vec();
+    assert_eq!(output_type, 424242u128.to_le_bytes().to_vec());
+
+    let decoded: WitnessArgs<LockPayload, [u8; 32], u128> = from_slice(&witness, false).unwrap();
+    assert_eq!(decoded, witness_args);
+}
+
+#[test]
+fn test_cell_witness_args() {
+    let cell = XUDTDataCell::new([1; 32], XUDTData { amount: 1 });
+    let witness_args = cell.get_witness_args().unwrap();
+    assert!(witness_args.lock().to_opt().is_none());
+    assert!(witness_args.input_type().to_opt().is_none());
+    assert!(witness_args.output_type().to_opt().is_none());
+
+    let cell = XUDTDataCell::from_arg(cell.get_lock_arg(), cell.get_type_arg(), cell.get_data(), cell.get_witness());
+    assert_eq!(cell.witness, Some(EmptyWitnessArgs::default()));
+}
+
+// fiber witnesses put their payload after an empty WitnessArgs rather than in one of its fields
+#[test]
+fn test_empty_witness_args_prefix() {
+    let witness = FundingWitness {
+        witness_args: EmptyWitnessArgs::default(),
+        version: 1,
+        funding_out_point: [4; 36],
+        pubkey: [5; 32],
+        signature: [6; 64],
+    };
+    let bytes = Encoding::Struct.encode(&witness).unwrap();
+    assert_eq!(bytes.len(), EMPTY_WITNESS_ARGS_LEN + 8 + 36 + 32 + 64);
+    assert_eq!(bytes[..EMPTY_WITNESS_ARGS_LEN], EmptyWitnessArgs::default().to_bytes().unwrap());
+    assert_eq!(bytes[16..24], 1u64.to_le_bytes());
+    assert_eq!(Encoding::Struct.decode::<FundingWitness>(&bytes).unwrap(), witness);
+
+    let mut broken = bytes.clone();
+    broken[0] ^= 0xff;
+    assert!(Encoding::Struct.decode::<FundingWitness>(&broken).is_err());
+    assert!(EmptyWitnessArgs::new(Some(vec![1]), None, None).to_bytes().is_err());
+}
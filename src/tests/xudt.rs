@@ -1,5 +1,4 @@
-use ckb_testtool::ckb_types::core::TransactionBuilder;
-use ckb_testtool::ckb_types::prelude::{Builder, Entity};
+use crate::capacity::Capacity;
 use crate::cells::xudt_data::{XUDTData, XUDTDataCell};
 use crate::{ContractUtil};
 
@@ -16,16 +15,14 @@ fn test_transfer_successful() {
 
     let mut ct = ContractUtil::new();
     let type_contract = ct.deploy_contract("XUDT");
-    let mut tx = TransactionBuilder::default().build();
-
-    tx = ct.add_input(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &input_token_cell, 100);
-    tx = ct.add_input(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &input_token2_cell, 100);
-
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &output_token1_cell, 100);
-    tx = ct.add_outpoint(tx, ct.alway_contract.clone(), Some(type_contract.clone()), &output_token2_cell, 100);
-
-
-    tx = ct.context.complete_tx(tx);
+    let always_success = ct.alway_contract.clone();
+
+    let tx = ct.tx()
+        .input(&always_success, Some(&type_contract), &input_token_cell, Capacity::shannons(100))
+        .input(&always_success, Some(&type_contract), &input_token2_cell, Capacity::shannons(100))
+        .output(&always_success, Some(&type_contract), &output_token1_cell, Capacity::shannons(100))
+        .output(&always_success, Some(&type_contract), &output_token2_cell, Capacity::shannons(100))
+        .build();
     let ret1 = ct.context.should_be_passed(&tx, 1000000);
     println!("ret:{:?}", ret1);
 }